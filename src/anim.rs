@@ -0,0 +1,336 @@
+
+use std::collections::HashMap;
+
+// Timed animation driven by presentation time: the `time` of each wl_surface.frame callback.
+// Nothing here reads a clock itself, so stepping with made-up timestamps is fully deterministic.
+// Timestamps are compositor milliseconds and may wrap, so they are only ever subtracted.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseInCubic,
+    EaseOutCubic,
+    EaseInOutCubic,
+    // Overshoots slightly before settling, for things popping into place
+    EaseOutBack,
+}
+
+impl Easing {
+    // t in 0.0..=1.0 -> progress, 0.0 at the start and 1.0 at the end
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInCubic => t * t * t,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutCubic => {
+                if t < 0.5 { 4.0 * t * t * t } else { 1.0 - ((-2.0 * t) + 2.0).powi(3) / 2.0 }
+            }
+            Easing::EaseOutBack => {
+                let c1 = 1.70158;
+                let c3 = c1 + 1.0;
+                1.0 + (c3 * (t - 1.0).powi(3)) + (c1 * (t - 1.0).powi(2))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tween {
+    pub from: f32,
+    pub to: f32,
+    pub duration_ms: u32,
+    pub easing: Easing,
+    // Set by the first step() after the tween is created, so it starts on the next frame
+    start_ms: Option<u32>,
+    value: f32,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration_ms: u32, easing: Easing) -> Tween {
+        Tween { from, to, duration_ms, easing, start_ms: None, value: from }
+    }
+
+    fn step(&mut self, now_ms: u32) -> bool {
+        let start_ms = *self.start_ms.get_or_insert(now_ms);
+        let elapsed = now_ms.wrapping_sub(start_ms);
+        if elapsed >= self.duration_ms {
+            self.value = self.to;
+            return false;
+        }
+        let progress = self.easing.apply(elapsed as f32 / self.duration_ms as f32);
+        self.value = self.from + ((self.to - self.from) * progress);
+        true
+    }
+}
+
+// Damped harmonic oscillator; retargeting keeps the current velocity so motion never jerks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spring {
+    pub value: f32,
+    pub velocity: f32,
+    pub target: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+    // Settled once both distance to target and speed (per second) are below this
+    pub precision: f32,
+    last_ms: Option<u32>,
+}
+
+// Integration step; frames longer than this are split up so stiff springs stay stable
+const SPRING_SUBSTEP_S: f32 = 1.0 / 240.0;
+// A frame gap longer than this (eg the surface was hidden) just snaps the spring to its target
+const SPRING_MAX_GAP_MS: u32 = 1000;
+
+impl Spring {
+    // Critically damped for the given stiffness: fastest approach without overshooting
+    pub fn critical(value: f32, target: f32, stiffness: f32, precision: f32) -> Spring {
+        Spring::new(value, target, stiffness, 2.0 * stiffness.sqrt(), precision)
+    }
+
+    pub fn new(value: f32, target: f32, stiffness: f32, damping: f32, precision: f32) -> Spring {
+        Spring { value, velocity: 0.0, target, stiffness, damping, precision, last_ms: None }
+    }
+
+    fn settled(&self) -> bool {
+        (self.target - self.value).abs() < self.precision && self.velocity.abs() < self.precision
+    }
+
+    fn step(&mut self, now_ms: u32) -> bool {
+        let last_ms = self.last_ms.replace(now_ms).unwrap_or(now_ms);
+        let gap_ms = now_ms.wrapping_sub(last_ms);
+        if gap_ms > SPRING_MAX_GAP_MS {
+            self.value = self.target;
+            self.velocity = 0.0;
+        }
+        let mut remaining_s = gap_ms.min(SPRING_MAX_GAP_MS) as f32 / 1000.0;
+        while remaining_s > 0.0 {
+            let dt = remaining_s.min(SPRING_SUBSTEP_S);
+            // Semi-implicit Euler
+            let accel = (self.stiffness * (self.target - self.value)) - (self.damping * self.velocity);
+            self.velocity += accel * dt;
+            self.value += self.velocity * dt;
+            remaining_s -= dt;
+        }
+        if self.settled() {
+            self.value = self.target;
+            self.velocity = 0.0;
+            return false;
+        }
+        true
+    }
+}
+
+// Repeating hops: 0.0 on the ground, 1.0 at the top of each hop.
+// Stopping early lets the current hop land rather than dropping the icon mid-air.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bounce {
+    pub hop_ms: u32,
+    pub hops: u32,
+    start_ms: Option<u32>,
+    elapsed_ms: u32,
+    value: f32,
+}
+
+impl Bounce {
+    pub fn new(hops: u32, hop_ms: u32) -> Bounce {
+        Bounce { hop_ms: hop_ms.max(1), hops, start_ms: None, elapsed_ms: 0, value: 0.0 }
+    }
+
+    fn stop(&mut self) {
+        self.hops = self.hops.min((self.elapsed_ms / self.hop_ms) + 1);
+    }
+
+    fn step(&mut self, now_ms: u32) -> bool {
+        let start_ms = *self.start_ms.get_or_insert(now_ms);
+        self.elapsed_ms = now_ms.wrapping_sub(start_ms);
+        if self.elapsed_ms >= self.hops * self.hop_ms {
+            self.value = 0.0;
+            return false;
+        }
+        // A parabola per hop, like something thrown straight up
+        let phase = (self.elapsed_ms % self.hop_ms) as f32 / self.hop_ms as f32;
+        self.value = 4.0 * phase * (1.0 - phase);
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anim {
+    Tween(Tween),
+    Spring(Spring),
+    Bounce(Bounce),
+}
+
+impl Anim {
+    pub fn value(&self) -> f32 {
+        match self {
+            Anim::Tween(tween) => tween.value,
+            Anim::Spring(spring) => spring.value,
+            Anim::Bounce(bounce) => bounce.value,
+        }
+    }
+}
+
+// What an animation is animating; one animation per key at a time
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AnimKey {
+    // 0.0 (flat) ..= 1.0 (fully magnified)
+    MagnifyAmount,
+    // Where along the dock the magnification is centered, trailing the pointer
+    MagnifyCenter,
+    // Keyed on DockItem::id(); 0.0 ..= 1.0 of the bounce height
+    LaunchBounce(String),
+    AttentionBounce(String),
+    // How far open a gap for a dragged item is, 0.0 ..= 1.0
+    DropGap(crate::layout::DropSlot),
+    // Progress of the puff of smoke left by a removed item
+    Poof,
+}
+
+// Finished animations keep reporting their final value until removed
+#[derive(Debug, Default)]
+pub struct Animations {
+    anims: HashMap<AnimKey, (Anim, bool)>,
+}
+
+impl Animations {
+    // Tween `key` to `to`, starting from wherever it is now (or `from` if it is not animated yet)
+    pub fn tween(&mut self, key: AnimKey, from: f32, to: f32, duration_ms: u32, easing: Easing) {
+        let from = self.value(&key).unwrap_or(from);
+        self.anims.insert(key, (Anim::Tween(Tween::new(from, to, duration_ms, easing)), true));
+    }
+
+    // Retarget an existing spring, or start `spring` if `key` is not a spring yet
+    pub fn spring_to(&mut self, key: AnimKey, target: f32, spring: Spring) {
+        let current = self.value(&key);
+        match self.anims.get_mut(&key) {
+            Some((Anim::Spring(existing), running)) => {
+                if existing.target != target {
+                    if !*running {
+                        existing.last_ms = None; // Don't integrate over the time it sat idle
+                    }
+                    existing.target = target;
+                    *running = true;
+                }
+            }
+            _ => {
+                let value = current.unwrap_or(spring.value);
+                self.anims.insert(key, (Anim::Spring(Spring { value, target, ..spring }), true));
+            }
+        }
+    }
+
+    // Start bouncing unless `key` is already mid-bounce, in which case it just keeps going
+    pub fn bounce(&mut self, key: AnimKey, hops: u32, hop_ms: u32) {
+        if let Some((Anim::Bounce(_), true)) = self.anims.get(&key) {
+            return;
+        }
+        self.anims.insert(key, (Anim::Bounce(Bounce::new(hops, hop_ms)), true));
+    }
+
+    // Finish the hop in progress, then stay on the ground
+    pub fn stop_bounce(&mut self, key: &AnimKey) {
+        if let Some((Anim::Bounce(bounce), _)) = self.anims.get_mut(key) {
+            bounce.stop();
+        }
+    }
+
+    // Jump straight to a value, cancelling any animation in progress
+    pub fn set(&mut self, key: AnimKey, value: f32) {
+        self.anims.insert(key, (Anim::Tween(Tween::new(value, value, 0, Easing::Linear)), false));
+    }
+
+    pub fn remove(&mut self, key: &AnimKey) {
+        self.anims.remove(key);
+    }
+
+    pub fn value(&self, key: &AnimKey) -> Option<f32> {
+        self.anims.get(key).map(|(anim, _)| anim.value())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.anims.values().any(|(_, running)| *running)
+    }
+
+    // Advance everything to `now_ms`; returns whether another frame is needed
+    pub fn step(&mut self, now_ms: u32) -> bool {
+        for (anim, running) in self.anims.values_mut() {
+            if !*running {
+                continue;
+            }
+            *running = match anim {
+                Anim::Tween(tween) => tween.step(now_ms),
+                Anim::Spring(spring) => spring.step(now_ms),
+                Anim::Bounce(bounce) => bounce.step(now_ms),
+            };
+        }
+        self.is_running()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_MS: u32 = 5000;
+
+    #[test]
+    fn tween_hits_start_midpoint_and_end_then_stops() {
+        let mut anims = Animations::default();
+        anims.tween(AnimKey::MagnifyAmount, 2.0, 10.0, 100, Easing::EaseOutCubic);
+        assert!(anims.step(START_MS));
+        assert_eq!(anims.value(&AnimKey::MagnifyAmount), Some(2.0));
+        assert!(anims.step(START_MS + 50));
+        let midpoint = 2.0 + (8.0 * Easing::EaseOutCubic.apply(0.5));
+        assert!((anims.value(&AnimKey::MagnifyAmount).unwrap() - midpoint).abs() < 1e-5);
+        assert!(!anims.step(START_MS + 100));
+        assert_eq!(anims.value(&AnimKey::MagnifyAmount), Some(10.0));
+        assert!(!anims.is_running());
+    }
+
+    #[test]
+    fn tween_survives_timestamp_wraparound() {
+        let mut anims = Animations::default();
+        anims.tween(AnimKey::Poof, 0.0, 1.0, 100, Easing::Linear);
+        anims.step(u32::MAX - 49);
+        anims.step(0);
+        assert!((anims.value(&AnimKey::Poof).unwrap() - 0.5).abs() < 1e-5);
+        assert!(!anims.step(50));
+        assert_eq!(anims.value(&AnimKey::Poof), Some(1.0));
+    }
+
+    #[test]
+    fn critical_spring_settles_without_overshoot() {
+        let precision = 0.001;
+        let mut anims = Animations::default();
+        anims.spring_to(AnimKey::MagnifyCenter, 100.0, Spring::critical(0.0, 0.0, 900.0, precision));
+        let mut now_ms = START_MS;
+        anims.step(now_ms);
+        let mut frames = 0;
+        while anims.step(now_ms) {
+            let value = anims.value(&AnimKey::MagnifyCenter).unwrap();
+            assert!(value <= 100.0 + precision, "overshot to {}", value);
+            now_ms += 16;
+            frames += 1;
+            assert!(frames < 120, "still moving after two seconds");
+        }
+        assert_eq!(anims.value(&AnimKey::MagnifyCenter), Some(100.0));
+        assert!(!anims.is_running());
+    }
+
+    #[test]
+    fn bounce_lands_after_all_hops() {
+        let key = AnimKey::LaunchBounce("app".to_string());
+        let mut anims = Animations::default();
+        anims.bounce(key.clone(), 3, 600);
+        assert!(anims.step(START_MS));
+        assert_eq!(anims.value(&key), Some(0.0));
+        // The top of the second hop
+        assert!(anims.step(START_MS + 900));
+        assert!((anims.value(&key).unwrap() - 1.0).abs() < 1e-5);
+        assert!(!anims.step(START_MS + (3 * 600)));
+        assert_eq!(anims.value(&key), Some(0.0));
+        assert!(!anims.is_running());
+    }
+}
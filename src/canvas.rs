@@ -0,0 +1,157 @@
+
+// Pixel helpers shared by everything drawn on top of the shelf.
+// All pixels are [b, g, r, a] with premultiplied alpha, matching wl_shm::Format::Argb8888.
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Image {
+    pub w: u32,
+    pub h: u32,
+    pub px: Vec<[u8; 4]>,
+}
+
+impl Image {
+    pub fn new(w: u32, h: u32) -> Image {
+        Image { w, h, px: vec![[0, 0, 0, 0]; (w * h) as usize] }
+    }
+
+    // Box-filtered when shrinking, bilinear when growing; icon themes rarely ship the exact size we want.
+    pub fn scaled(&self, new_w: u32, new_h: u32) -> Image {
+        let mut out = Image::new(new_w, new_h);
+        if self.w == 0 || self.h == 0 || new_w == 0 || new_h == 0 {
+            return out;
+        }
+        let x_ratio = self.w as f32 / new_w as f32;
+        let y_ratio = self.h as f32 / new_h as f32;
+        for y in 0..new_h {
+            for x in 0..new_w {
+                let px = if x_ratio > 1.0 || y_ratio > 1.0 {
+                    self.box_sample(x as f32 * x_ratio, y as f32 * y_ratio, x_ratio.max(1.0), y_ratio.max(1.0))
+                }
+                else {
+                    self.bilinear_sample(((x as f32 + 0.5) * x_ratio) - 0.5, ((y as f32 + 0.5) * y_ratio) - 0.5)
+                };
+                out.px[((y * new_w) + x) as usize] = px;
+            }
+        }
+        out
+    }
+
+    fn get(&self, x: i32, y: i32) -> [u8; 4] {
+        let x = x.clamp(0, self.w as i32 - 1);
+        let y = y.clamp(0, self.h as i32 - 1);
+        self.px[((y as u32 * self.w) + x as u32) as usize]
+    }
+
+    fn box_sample(&self, x0: f32, y0: f32, box_w: f32, box_h: f32) -> [u8; 4] {
+        let mut sum = [0u32; 4];
+        let mut count = 0;
+        for sy in (y0 as i32)..((y0 + box_h).ceil() as i32).max(y0 as i32 + 1) {
+            for sx in (x0 as i32)..((x0 + box_w).ceil() as i32).max(x0 as i32 + 1) {
+                let p = self.get(sx, sy);
+                for c in 0..4 {
+                    sum[c] += p[c] as u32;
+                }
+                count += 1;
+            }
+        }
+        [(sum[0] / count) as u8, (sum[1] / count) as u8, (sum[2] / count) as u8, (sum[3] / count) as u8]
+    }
+
+    fn bilinear_sample(&self, x: f32, y: f32) -> [u8; 4] {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let p00 = self.get(x0 as i32, y0 as i32);
+        let p10 = self.get(x0 as i32 + 1, y0 as i32);
+        let p01 = self.get(x0 as i32, y0 as i32 + 1);
+        let p11 = self.get(x0 as i32 + 1, y0 as i32 + 1);
+        let mut out = [0u8; 4];
+        for c in 0..4 {
+            let top = (p00[c] as f32 * (1.0 - fx)) + (p10[c] as f32 * fx);
+            let bottom = (p01[c] as f32 * (1.0 - fx)) + (p11[c] as f32 * fx);
+            out[c] = ((top * (1.0 - fy)) + (bottom * fy)).round() as u8;
+        }
+        out
+    }
+}
+
+pub struct Canvas<'a> {
+    pub px: &'a mut [[u8; 4]],
+    pub w: i32,
+    pub h: i32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(px: &'a mut [[u8; 4]], w: u32, h: u32) -> Canvas<'a> {
+        Canvas { px, w: w as i32, h: h as i32 }
+    }
+
+    // Porter-Duff "over" with an extra opacity multiplier on the source
+    pub fn blend(&mut self, x: i32, y: i32, src: [u8; 4], opacity: f32) {
+        if x < 0 || y < 0 || x >= self.w || y >= self.h {
+            return;
+        }
+        let opacity = opacity.clamp(0.0, 1.0);
+        let src_a = src[3] as f32 * opacity;
+        if src_a <= 0.0 {
+            return;
+        }
+        let dst = &mut self.px[((y * self.w) + x) as usize];
+        let inv_a = 1.0 - (src_a / 255.0);
+        for c in 0..3 {
+            dst[c] = ((src[c] as f32 * opacity) + (dst[c] as f32 * inv_a)).round().min(255.0) as u8;
+        }
+        dst[3] = (src_a + (dst[3] as f32 * inv_a)).round().min(255.0) as u8;
+    }
+
+    pub fn blit(&mut self, img: &Image, x: i32, y: i32, opacity: f32) {
+        for iy in 0..img.h as i32 {
+            for ix in 0..img.w as i32 {
+                self.blend(x + ix, y + iy, img.px[((iy * img.w as i32) + ix) as usize], opacity);
+            }
+        }
+    }
+
+    // Soft radial light, brightest at the center and fading to nothing at `radius`
+    pub fn glow(&mut self, cx: f32, cy: f32, radius: f32, [b, g, r]: [u8; 3], intensity: f32) {
+        let r_i = radius.ceil() as i32;
+        for y in (cy as i32 - r_i)..=(cy as i32 + r_i) {
+            for x in (cx as i32 - r_i)..=(cx as i32 + r_i) {
+                let dist = (((x as f32 + 0.5) - cx).powi(2) + ((y as f32 + 0.5) - cy).powi(2)).sqrt();
+                if dist >= radius {
+                    continue;
+                }
+                let falloff = (1.0 - (dist / radius)).powi(2) * intensity;
+                self.blend(x, y, [b, g, r, 0xFF], falloff);
+            }
+        }
+    }
+
+    pub fn fill_rounded_rect(&mut self, x: i32, y: i32, w: i32, h: i32, radius: i32, color: [u8; 4]) {
+        for py in y..(y + h) {
+            for px in x..(x + w) {
+                let coverage = rounded_rect_coverage(px - x, py - y, w, h, radius);
+                if coverage > 0.0 {
+                    self.blend(px, py, color, coverage);
+                }
+            }
+        }
+    }
+}
+
+// Fraction of pixel (px, py) inside a w*h rectangle with rounded corners; used for cheap anti-aliasing.
+// Pixels outside the rectangle are 0.0, so callers can test pixels around it too.
+pub fn rounded_rect_coverage(px: i32, py: i32, w: i32, h: i32, radius: i32) -> f32 {
+    if px < 0 || py < 0 || px >= w || py >= h {
+        return 0.0;
+    }
+    let radius = radius.min(w / 2).min(h / 2).max(0);
+    let cx = if px < radius { radius } else if px >= w - radius { w - radius - 1 } else { px };
+    let cy = if py < radius { radius } else if py >= h - radius { h - radius - 1 } else { py };
+    if cx == px || cy == py {
+        return 1.0;
+    }
+    let dist = (((px - cx) as f32).powi(2) + ((py - cy) as f32).powi(2)).sqrt();
+    (radius as f32 + 0.5 - dist).clamp(0.0, 1.0)
+}
@@ -0,0 +1,158 @@
+
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+// Everything the user can set, stored in $XDG_CONFIG_HOME/sdock/config.toml:
+//
+//   click_policy = "minimize"
+//   theme = "leopard"                 # name in a theme dir, or a path to a .toml file
+//   style = "3d"                      # "3d" glass shelf or "2d" flat dock
+//   edge = "bottom"                   # "bottom", "top", "left" or "right"; needs a restart
+//
+//   [magnify]
+//   enabled = true
+//   max_scale = 1.8                   # icon size under the pointer; raising it needs a restart
+//   range = 3.0                       # icon widths either side of the pointer
+//
+//   [[pinned]]
+//   id = "org.mozilla.firefox"        # desktop file id
+//   label = "Web"                     # optional overrides
+//   icon = "/home/me/icons/web.png"
+//   command = "firefox --private-window"
+//
+//   [[pinned]]
+//   id = "/home/me/bin/tool.desktop"  # or a desktop file outside the XDG data dirs
+//
+//   [[pinned]]
+//   folder = "/home/me/Downloads"     # clicking shows its files in a stack
+//   view = "grid"                     # "auto", "fan" or "grid"
+//   sort = "date"                     # "name" or "date"
+//
+//   [[pinned]]
+//   url = "https://example.org"       # opens with its default handler
+//
+//   [[pinned]]
+//   trash = true                      # the trash; there by default
+//
+// Pinned items appear on the dock in file order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub click_policy: crate::dock::ClickPolicy,
+    pub theme: String,
+    pub style: crate::layout::DockStyle,
+    pub edge: crate::layout::Edge,
+    pub magnify: MagnifyConfig,
+    pub pinned: Vec<PinnedItem>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            click_policy: crate::dock::ClickPolicy::default(),
+            theme: crate::theme::DEFAULT_THEME_NAME.to_string(),
+            style: crate::layout::DockStyle::default(),
+            edge: crate::layout::Edge::default(),
+            magnify: MagnifyConfig::default(),
+            pinned: vec![PinnedItem { trash: true, ..Default::default() }],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MagnifyConfig {
+    pub enabled: bool,
+    pub max_scale: f32,
+    pub range: f32,
+}
+
+impl Default for MagnifyConfig {
+    fn default() -> MagnifyConfig {
+        MagnifyConfig { enabled: false, max_scale: 1.8, range: 3.0 }
+    }
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PinnedItem {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub trash: bool,
+    // Folders only: how clicking shows what is in them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub view: Option<crate::stack::StackView>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<crate::stack::StackSort>,
+    #[serde(flatten)]
+    pub overrides: ItemOverrides,
+}
+
+// Per-item replacements for what the desktop entry says
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ItemOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+}
+
+pub fn config_dir() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home).join("sdock"),
+        _ => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config").join("sdock"),
+    }
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+impl Config {
+    // How much deeper than the shelf the surface has to be for bouncing and magnified icons
+    pub fn surface_scale(&self) -> f32 {
+        let magnified = if self.magnify.enabled { self.magnify.max_scale } else { 1.0 };
+        crate::layout::surface_scale(magnified.max(1.0 + crate::layout::BOUNCE_HEIGHT))
+    }
+
+    // A missing file is not an error; it just means nothing has been pinned yet.
+    pub fn load() -> Result<Config, Box<dyn std::error::Error>> {
+        let path = config_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = std::fs::read_to_string(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+        let config = toml::from_str::<Config>(&contents).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+        if !(1.0..=4.0).contains(&config.magnify.max_scale) {
+            return Err(format!("{:?}: magnify.max_scale = {} is outside the allowed range 1..=4", path, config.magnify.max_scale).into());
+        }
+        if !(0.5..=16.0).contains(&config.magnify.range) {
+            return Err(format!("{:?}: magnify.range = {} is outside the allowed range 0.5..=16", path, config.magnify.range).into());
+        }
+        Ok(config)
+    }
+
+    // Written to a temporary file then renamed over the original so a crash
+    // (or the file watcher) never sees a half-written config.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let path = config_path();
+        let contents = toml::to_string_pretty(self).map_err(crate::err::eloc!())?;
+        std::fs::create_dir_all(config_dir()).map_err(crate::err::eloc!(format!("{:?}", config_dir())))?;
+
+        let tmp_path = path.with_extension("toml.tmp");
+        let mut tmp = std::fs::File::create(&tmp_path).map_err(crate::err::eloc!(format!("{:?}", tmp_path)))?;
+        tmp.write_all(contents.as_bytes()).map_err(crate::err::eloc!(format!("{:?}", tmp_path)))?;
+        tmp.sync_all().map_err(crate::err::eloc!(format!("{:?}", tmp_path)))?;
+        std::fs::rename(&tmp_path, &path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,493 @@
+
+use std::path::{Path, PathBuf};
+
+// Parsed subset of a freedesktop .desktop file; see
+// https://specifications.freedesktop.org/desktop-entry-spec/latest/
+#[derive(Debug, Clone, Default)]
+pub struct DesktopEntry {
+    pub id: String, // file name minus ".desktop", eg "org.gnome.Nautilus"
+    pub path: PathBuf,
+    pub name: String,
+    pub exec: Option<String>,
+    pub icon: Option<String>,
+    pub startup_wm_class: Option<String>,
+    pub no_display: bool,
+    // The [Desktop Action x] groups named by Actions=, in that order
+    pub actions: Vec<DesktopAction>,
+    // MimeType=: the file types it can open, eg "image/png" or "x-scheme-handler/https"
+    pub mime_types: Vec<String>,
+}
+
+// Extra entry points such as "New Private Window"
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DesktopAction {
+    pub id: String,
+    pub name: String,
+    pub exec: Option<String>,
+}
+
+impl DesktopEntry {
+    // Does a toplevel with the given app_id belong to this entry?
+    pub fn matches_app_id(&self, app_id: &str) -> bool {
+        app_id_matches(&self.id, self.startup_wm_class.as_deref(), app_id)
+    }
+}
+
+pub fn app_id_matches(desktop_id: &str, startup_wm_class: Option<&str>, app_id: &str) -> bool {
+    if app_id.is_empty() {
+        return false;
+    }
+    if desktop_id.eq_ignore_ascii_case(app_id) {
+        return true;
+    }
+    if let Some(wm_class) = startup_wm_class && wm_class.eq_ignore_ascii_case(app_id) {
+        return true;
+    }
+    // Reverse-DNS ids such as "org.mozilla.firefox" vs app_id "firefox"
+    if let Some(last_segment) = desktop_id.rsplit('.').next() && last_segment.eq_ignore_ascii_case(app_id) {
+        return true;
+    }
+    false
+}
+
+pub fn parse(path: &Path) -> Result<DesktopEntry, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    let id = path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut entry = DesktopEntry {
+        id,
+        path: path.to_path_buf(),
+        ..Default::default()
+    };
+
+    enum Group { Main, Action(usize), Other }
+    let mut group = Group::Other;
+    // Name= and each Name[locale]= compete; lower ranks are better matches for the user's locale
+    let locales = locale_suffixes();
+    let rank_of = |locale: Option<&str>| match locale {
+        Some(locale) => locales.iter().position(|l| l == locale),
+        None => Some(locales.len()),
+    };
+    let mut name_rank = usize::MAX;
+    let mut action_name_ranks: Vec<usize> = vec![];
+    let mut action_ids: Vec<String> = vec![];
+    let mut actions: Vec<DesktopAction> = vec![];
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            group = match line.strip_prefix("[Desktop Action ").and_then(|rest| rest.strip_suffix(']')) {
+                Some(id) => {
+                    actions.push(DesktopAction { id: id.to_string(), ..Default::default() });
+                    action_name_ranks.push(usize::MAX);
+                    Group::Action(actions.len() - 1)
+                }
+                None if line == "[Desktop Entry]" => Group::Main,
+                None => Group::Other,
+            };
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().to_string()),
+            None => continue,
+        };
+        // "Name[de_DE]" -> ("Name", Some("de_DE"))
+        let (key, locale) = match key.split_once('[') {
+            Some((key, locale)) => (key, Some(locale.trim_end_matches(']'))),
+            None => (key, None),
+        };
+        if key == "Name" {
+            let rank = match rank_of(locale) {
+                Some(rank) => rank,
+                None => continue, // Some other language
+            };
+            match group {
+                Group::Main if rank < name_rank => {
+                    entry.name = value;
+                    name_rank = rank;
+                }
+                Group::Action(i) if rank < action_name_ranks[i] => {
+                    actions[i].name = value;
+                    action_name_ranks[i] = rank;
+                }
+                _ => { }
+            }
+            continue;
+        }
+        if locale.is_some() {
+            continue; // Only names are shown, so only names are localized
+        }
+        match group {
+            Group::Main => match key {
+                "Exec" => entry.exec = Some(value),
+                "Icon" => entry.icon = Some(value),
+                "StartupWMClass" => entry.startup_wm_class = Some(value),
+                "NoDisplay" => entry.no_display = value == "true",
+                "Actions" => action_ids = value.split(';').filter(|id| !id.is_empty()).map(str::to_string).collect(),
+                "MimeType" => entry.mime_types = value.split(';').filter(|m| !m.is_empty()).map(str::to_string).collect(),
+                _ => { }
+            },
+            Group::Action(i) if key == "Exec" => actions[i].exec = Some(value),
+            _ => { }
+        }
+    }
+    // Groups not listed in Actions= are ignored, per the spec
+    entry.actions = action_ids.iter()
+        .filter_map(|id| actions.iter().find(|a| &a.id == id && !a.name.is_empty()).cloned())
+        .collect();
+
+    if entry.name.is_empty() {
+        entry.name = entry.id.clone();
+    }
+
+    Ok(entry)
+}
+
+// Localized key suffixes to look for, best match first. For LANG=de_DE.UTF-8@euro that is
+// de_DE@euro, de_DE, de@euro, de; see "Localized values for keys" in the spec.
+pub fn locale_suffixes() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale.as_str(), None),
+    };
+    let rest = rest.split('.').next().unwrap_or(""); // The encoding never takes part in matching
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return vec![];
+    }
+    let mut suffixes = vec![];
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        suffixes.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        suffixes.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        suffixes.push(format!("{}@{}", lang, modifier));
+    }
+    suffixes.push(lang.to_string());
+    suffixes
+}
+
+// $XDG_DATA_HOME followed by $XDG_DATA_DIRS, in priority order
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![];
+    match std::env::var_os("XDG_DATA_HOME") {
+        Some(data_home) if !data_home.is_empty() => dirs.push(PathBuf::from(data_home)),
+        _ => {
+            if let Some(home) = std::env::var_os("HOME") {
+                dirs.push(PathBuf::from(home).join(".local/share"));
+            }
+        }
+    }
+    let data_dirs = std::env::var("XDG_DATA_DIRS").unwrap_or_default();
+    let data_dirs = if data_dirs.is_empty() { "/usr/local/share:/usr/share".to_string() } else { data_dirs };
+    for dir in data_dirs.split(':') {
+        if !dir.is_empty() {
+            dirs.push(PathBuf::from(dir));
+        }
+    }
+    dirs
+}
+
+// Every desktop entry visible to the user, loaded once at startup.
+#[derive(Debug, Default)]
+pub struct DesktopIndex {
+    pub entries: Vec<DesktopEntry>,
+}
+
+impl DesktopIndex {
+    pub fn load() -> DesktopIndex {
+        let mut index = DesktopIndex::default();
+        for data_dir in data_dirs() {
+            index.load_dir(&data_dir.join("applications"));
+        }
+        eprintln!("{}:{} indexed {} desktop entries", file!(), line!(), index.entries.len());
+        index
+    }
+
+    fn load_dir(&mut self, dir: &Path) {
+        let read_dir = match std::fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(_) => return, // Most XDG_DATA_DIRS do not have every sub-directory
+        };
+        for dir_entry in read_dir.flatten() {
+            let path = dir_entry.path();
+            if path.is_dir() {
+                self.load_dir(&path);
+            }
+            else if path.extension().map(|e| e == "desktop").unwrap_or(false) {
+                match parse(&path) {
+                    Ok(entry) => {
+                        // Earlier data dirs take priority over later ones
+                        if !self.entries.iter().any(|e| e.id == entry.id) {
+                            self.entries.push(entry);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("{}:{} {}", file!(), line!(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn by_id(&self, id: &str) -> Option<&DesktopEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    pub fn for_app_id(&self, app_id: &str) -> Option<&DesktopEntry> {
+        if let Some(entry) = self.by_id(app_id) {
+            return Some(entry);
+        }
+        // Prefer visible entries; NoDisplay ones are often helpers sharing a WM class
+        self.entries.iter().find(|e| !e.no_display && e.matches_app_id(app_id))
+            .or_else(|| self.entries.iter().find(|e| e.matches_app_id(app_id)))
+    }
+}
+
+// Split an Exec= value into argv, following the quoting rules of the spec and
+// dropping field codes (%f, %U, %i, ...) since there are no files to substitute.
+pub fn exec_argv(exec: &str) -> Vec<String> {
+    expand_exec(exec, &[]).into_iter().next().unwrap_or_default()
+}
+
+// One argv per process to start for opening `uris`. %F/%U take every file in one process,
+// %f/%u take one file each, so several files there mean several launches. Non-file URIs are
+// skipped for %f/%F. Other field codes (%i, %c, %k) are dropped.
+pub fn expand_exec(exec: &str, uris: &[String]) -> Vec<Vec<String>> {
+    let tokens = exec_tokens(exec);
+    let has_single_file_code = tokens.iter().flatten().any(|p| matches!(p, ExecPiece::Field('f' | 'u')));
+    let one_each: Vec<Option<&String>> = if has_single_file_code && !uris.is_empty() {
+        uris.iter().map(Some).collect()
+    }
+    else {
+        vec![None]
+    };
+    let paths = || uris.iter().filter_map(|uri| uri_to_path(uri)).map(|p| p.to_string_lossy().to_string());
+
+    one_each.into_iter().filter_map(|uri| {
+        let mut argv = vec![];
+        for token in tokens.iter() {
+            // A lone %F or %U becomes one argument per file
+            match token.as_slice() {
+                [ExecPiece::Field('F')] => {
+                    argv.extend(paths());
+                    continue;
+                }
+                [ExecPiece::Field('U')] => {
+                    argv.extend(uris.iter().cloned());
+                    continue;
+                }
+                _ => { }
+            }
+            let mut arg = String::new();
+            for piece in token {
+                match (piece, uri) {
+                    (ExecPiece::Text(text), _) => arg.push_str(text),
+                    (ExecPiece::Field('f'), Some(uri)) => arg.push_str(&uri_to_path(uri)?.to_string_lossy()),
+                    (ExecPiece::Field('u'), Some(uri)) => arg.push_str(uri),
+                    (ExecPiece::Field(_), _) => { }
+                }
+            }
+            if !arg.is_empty() {
+                argv.push(arg);
+            }
+        }
+        Some(argv)
+    }).collect()
+}
+
+// Does the Exec= line have anywhere to put files?
+pub fn exec_accepts_files(exec: &str) -> bool {
+    exec_tokens(exec).iter().flatten().any(|p| matches!(p, ExecPiece::Field('f' | 'F' | 'u' | 'U')))
+}
+
+enum ExecPiece {
+    Text(String),
+    Field(char),
+}
+
+// Whitespace-separated arguments, each a run of literal text and field codes
+fn exec_tokens(exec: &str) -> Vec<Vec<ExecPiece>> {
+    let mut tokens = vec![];
+    let mut current = vec![];
+    let mut text = String::new();
+    let mut in_token = false;
+    let mut in_quotes = false;
+    let mut chars = exec.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_token = true;
+            }
+            '\\' if in_quotes => {
+                if let Some(escaped) = chars.next() {
+                    text.push(escaped);
+                }
+            }
+            '%' => {
+                match chars.next() {
+                    Some('%') => text.push('%'),
+                    Some(code) => {
+                        if !text.is_empty() {
+                            current.push(ExecPiece::Text(std::mem::take(&mut text)));
+                        }
+                        current.push(ExecPiece::Field(code));
+                    }
+                    None => { }
+                }
+                in_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !text.is_empty() {
+                    current.push(ExecPiece::Text(std::mem::take(&mut text)));
+                }
+                if in_token && !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                in_token = false;
+            }
+            c => {
+                text.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if !text.is_empty() {
+        current.push(ExecPiece::Text(text));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+// "file:///home/me/My%20File.txt" -> /home/me/My File.txt; None for other schemes
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // An authority other than localhost would be someone else's machine
+    let path = match rest.strip_prefix("localhost") {
+        Some(path) => path,
+        None => rest,
+    };
+    if !path.starts_with('/') {
+        return None;
+    }
+    use std::os::unix::ffi::OsStringExt;
+    Some(PathBuf::from(std::ffi::OsString::from_vec(percent_decode(path))))
+}
+
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+// The reverse for a path: bytes other than unreserved characters and '/' become %XX
+pub fn percent_encode_path(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut out = String::new();
+    for &b in path.as_os_str().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+// Fire-and-forget; a thread reaps the child so it never lingers as a zombie.
+pub fn spawn_argv(argv: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (program, args) = match argv.split_first() {
+        Some(split) => split,
+        None => return Err("Cannot launch an empty command".into()),
+    };
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .spawn()
+        .map_err(crate::err::eloc!(format!("{:?}", argv)))?;
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+// $XDG_CONFIG_HOME/autostart, where the session starts entries from at login; see
+// https://specifications.freedesktop.org/autostart-spec/latest/
+pub fn autostart_dir() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home).join("autostart"),
+        _ => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".config").join("autostart"),
+    }
+}
+
+// A copy of the entry in the autostart dir counts, unless it was disabled with Hidden=true
+pub fn is_autostart(id: &str) -> bool {
+    let path = autostart_dir().join(format!("{}.desktop", id));
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => !contents.lines().any(|line| line.trim().replace(' ', "") == "Hidden=true"),
+        Err(_) => false,
+    }
+}
+
+pub fn set_autostart(entry: &DesktopEntry, enabled: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = autostart_dir();
+    let path = dir.join(format!("{}.desktop", entry.id));
+    if enabled {
+        std::fs::create_dir_all(&dir).map_err(crate::err::eloc!(format!("{:?}", dir)))?;
+        std::fs::copy(&entry.path, &path).map_err(crate::err::eloc!(format!("{:?} -> {:?}", entry.path, path)))?;
+    }
+    else if path.exists() {
+        std::fs::remove_file(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    }
+    Ok(())
+}
+
+// Ask the file manager to open the containing folder with `path` selected, falling back
+// to just opening the folder when nothing implements org.freedesktop.FileManager1
+pub fn show_in_file_manager(path: &Path) {
+    let uri = format!("file://{}", percent_encode_path(path));
+    let folder = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+    std::thread::spawn(move || {
+        let shown = std::process::Command::new("dbus-send")
+            .args(["--session", "--print-reply", "--dest=org.freedesktop.FileManager1", "--type=method_call",
+                   "/org/freedesktop/FileManager1", "org.freedesktop.FileManager1.ShowItems",
+                   &format!("array:string:{}", uri), "string:"])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false);
+        if !shown && let Err(e) = spawn_argv(&["xdg-open".to_string(), folder.to_string_lossy().to_string()]) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+    });
+}
@@ -0,0 +1,251 @@
+// Drag and drop from other clients: files dragged out of a file manager and dropped on an
+// app's icon open with that app, and apps, folders and links dropped between icons are added
+// to the dock. wl_data_device delivers the drag; the dragged files arrive
+// as a text/uri-list the source writes into a pipe we hand it. The pipe is read from the
+// main poll loop as data comes in, so a slow source never stalls the dock.
+
+use std::io::Read;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
+
+use wayland_client::{
+    event_created_child,
+    protocol::{wl_data_device, wl_data_device_manager, wl_data_offer, wl_registry, wl_seat},
+    Connection, Dispatch, Proxy, QueueHandle,
+};
+
+use crate::dock::DockItem;
+use crate::layout::DropSlot;
+use crate::State;
+
+pub const URI_LIST: &str = "text/uri-list";
+
+#[derive(Debug, Default)]
+pub struct DataDevice {
+    pub manager: Option<wl_data_device_manager::WlDataDeviceManager>,
+    pub device: Option<wl_data_device::WlDataDevice>,
+    // Offers announced by data_offer, with the mime types each has listed so far;
+    // they stay here until an enter or selection event says what they are for
+    announced: Vec<(wl_data_offer::WlDataOffer, Vec<String>)>,
+    // The drag currently over the dock, if it carries files
+    pub drag: Option<DndDrag>,
+}
+
+// A drag from another client that is over the dock surface
+#[derive(Debug)]
+pub struct DndDrag {
+    offer: wl_data_offer::WlDataOffer,
+    // The enter event's serial, which every accept request must repeat
+    serial: u32,
+    // Set until the source has finished writing the URIs; nothing is accepted before then
+    receiving: Option<Receiving>,
+    pub uris: Vec<String>,
+    // One per URI, from MimeDb::for_uri
+    pub mime_types: Vec<String>,
+    // Surface coordinates of the drag pointer
+    pub pointer: (f64, f64),
+    // DockItem::id() of the item the files would open with
+    pub target: Option<String>,
+    // What dropping on the shelf would add, one per URI; empty if any of them can't be added
+    pub adds: Vec<DockItem>,
+    // Where they would be added
+    pub slot: Option<DropSlot>,
+    // Every gap still open or closing, including the slot's
+    pub gaps: Vec<DropSlot>,
+}
+
+// The read end of the pipe the source is writing the URI list into, and what has arrived so far
+#[derive(Debug)]
+struct Receiving {
+    file: std::fs::File,
+    contents: Vec<u8>,
+}
+
+impl DndDrag {
+    pub fn is_receiving(&self) -> bool {
+        self.receiving.is_some()
+    }
+
+    pub fn accepted(&self) -> bool {
+        self.target.is_some() || self.slot.is_some()
+    }
+
+    // Tell the source whether dropping here does anything, so it can show the right cursor
+    pub fn accept(&self) {
+        let mime_type = if self.accepted() { Some(URI_LIST.to_string()) } else { None };
+        self.offer.accept(self.serial, mime_type);
+    }
+}
+
+impl DataDevice {
+    pub fn bind(&mut self, registry: &wl_registry::WlRegistry, name: u32, version: u32, qh: &QueueHandle<State>) {
+        // v3 adds the drop actions and wl_data_offer.finish
+        self.manager = Some(registry.bind::<wl_data_device_manager::WlDataDeviceManager, _, _>(name, version.min(3), qh, ()));
+    }
+
+    // The device needs both the manager and the seat, which the registry may announce in either order
+    pub fn get_device(&mut self, seat: Option<&wl_seat::WlSeat>, qh: &QueueHandle<State>) {
+        if let (Some(manager), Some(seat), None) = (self.manager.as_ref(), seat, self.device.as_ref()) {
+            self.device = Some(manager.get_data_device(seat, qh, ()));
+        }
+    }
+
+    // For poll_dispatch to wait on while the dragged URIs are on their way
+    pub fn receiving_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.drag.as_ref()?.receiving.as_ref().map(|r| r.file.as_fd())
+    }
+
+    // Read whatever the source has written so far. Returns the URIs once it closes its end
+    // of the pipe, or None while more is still to come.
+    pub fn read_uris(&mut self) -> Option<Result<Vec<String>, Box<dyn std::error::Error>>> {
+        let receiving = self.drag.as_mut()?.receiving.as_mut()?;
+        let mut chunk = [0u8; 4096];
+        let result = loop {
+            match receiving.file.read(&mut chunk) {
+                Ok(0) => break Ok(()),
+                Ok(n) => receiving.contents.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return None,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => { }
+                Err(e) => break Err(e),
+            }
+        };
+        let receiving = self.drag.as_mut()?.receiving.take()?;
+        Some(match result {
+            Ok(()) => Ok(parse_uri_list(&String::from_utf8_lossy(&receiving.contents))),
+            Err(e) => Err(format!("{}:{} reading the dragged {}: {}", file!(), line!(), URI_LIST, e).into()),
+        })
+    }
+
+    // Forget the current drag, whether it left or was dropped
+    pub fn end_drag(&mut self) -> Option<DndDrag> {
+        let drag = self.drag.take();
+        if let Some(drag) = drag.as_ref() {
+            drag.offer.destroy();
+        }
+        drag
+    }
+}
+
+// Ask the source for the dragged URIs; the request goes out with the main loop's next flush.
+// Only our end of the pipe is made non-blocking: the flag belongs to the open file, which the
+// source's end would share.
+fn start_receive(offer: &wl_data_offer::WlDataOffer) -> Result<Receiving, Box<dyn std::error::Error>> {
+    use nix::fcntl::{fcntl, FcntlArg, OFlag};
+
+    let (read_end, write_end) = nix::unistd::pipe2(OFlag::O_CLOEXEC).map_err(crate::err::eloc!())?;
+    fcntl(read_end.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(crate::err::eloc!())?;
+    offer.receive(URI_LIST.to_string(), write_end.as_fd());
+    drop(write_end); // Otherwise our own copy keeps the pipe open and we never see EOF
+    Ok(Receiving { file: std::fs::File::from(read_end), contents: vec![] })
+}
+
+// RFC 2483: one URI per CRLF-terminated line, with # comment lines
+pub fn parse_uri_list(list: &str) -> Vec<String> {
+    list.lines()
+        .map(|l| l.trim_end_matches('\r').trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+impl Dispatch<wl_data_device_manager::WlDataDeviceManager, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_data_device_manager::WlDataDeviceManager,
+        _: wl_data_device_manager::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // No events
+    }
+}
+
+impl Dispatch<wl_data_device::WlDataDevice, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_data_device::WlDataDevice,
+        event: wl_data_device::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_data_device::Event::DataOffer { id } => {
+                state.dnd.announced.push((id, vec![]));
+            }
+            wl_data_device::Event::Enter { serial, surface, x, y, id } => {
+                let offer = match id {
+                    Some(offer) => offer,
+                    None => return, // A drag within some other client's surfaces; nothing for us
+                };
+                let mime_types = match state.dnd.announced.iter().position(|(o, _)| *o == offer) {
+                    Some(i) => state.dnd.announced.remove(i).1,
+                    None => vec![],
+                };
+                // Only the dock itself takes drops, and only of files
+                if state.base_surface.as_ref() != Some(&surface) || !mime_types.iter().any(|m| m == URI_LIST) {
+                    offer.accept(serial, None);
+                    offer.destroy();
+                    return;
+                }
+                let receiving = match start_receive(&offer) {
+                    Ok(receiving) => receiving,
+                    Err(e) => {
+                        eprintln!("{}:{} {}", file!(), line!(), e);
+                        offer.accept(serial, None);
+                        offer.destroy();
+                        return;
+                    }
+                };
+                if offer.version() >= 3 {
+                    offer.set_actions(wl_data_device_manager::DndAction::Copy, wl_data_device_manager::DndAction::Copy);
+                }
+                state.dnd.drag = Some(DndDrag {
+                    offer, serial, receiving: Some(receiving), uris: vec![], mime_types: vec![], pointer: (x, y),
+                    target: None, adds: vec![], slot: None, gaps: vec![],
+                });
+                state.on_dnd_enter((x, y));
+            }
+            wl_data_device::Event::Motion { x, y, .. } if state.dnd.drag.is_some() => {
+                state.on_dnd_motion((x, y));
+            }
+            wl_data_device::Event::Leave if let Some(drag) = state.dnd.end_drag() => {
+                state.on_dnd_leave(&drag);
+            }
+            wl_data_device::Event::Drop if let Some(drag) = state.dnd.drag.take() => {
+                state.on_dnd_drop(&drag);
+                if drag.offer.version() >= 3 && drag.accepted() {
+                    drag.offer.finish();
+                }
+                drag.offer.destroy();
+                state.on_dnd_leave(&drag);
+            }
+            // The clipboard; the dock never pastes
+            wl_data_device::Event::Selection { id: Some(offer) } => {
+                state.dnd.announced.retain(|(o, _)| *o != offer);
+                offer.destroy();
+            }
+            _ => { }
+        }
+    }
+
+    event_created_child!(State, wl_data_device::WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (wl_data_offer::WlDataOffer, ()),
+    ]);
+}
+
+impl Dispatch<wl_data_offer::WlDataOffer, ()> for State {
+    fn event(
+        state: &mut Self,
+        offer: &wl_data_offer::WlDataOffer,
+        event: wl_data_offer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // The mime types are listed right after data_offer, before enter says what the offer is for
+        if let wl_data_offer::Event::Offer { mime_type } = event && let Some((_, types)) = state.dnd.announced.iter_mut().find(|(o, _)| o == offer) {
+            types.push(mime_type);
+        }
+    }
+}
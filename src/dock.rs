@@ -0,0 +1,560 @@
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::{Canvas, Image};
+use crate::config::{ItemOverrides, PinnedItem};
+use crate::desktop::{DesktopAction, DesktopEntry, DesktopIndex};
+use crate::icons::IconCache;
+use crate::layout::{DockLayout, DockStyle, Edge, Rect};
+use crate::mime::MimeDb;
+use crate::stack::{StackOptions, StackSort, StackView};
+use crate::text::TextRenderer;
+use crate::theme::Theme;
+use crate::toplevel::{Toplevel, Toplevels};
+
+// What a left click on an already-running app does once its newest window is focused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClickPolicy {
+    // Focus the newest window; clicking again minimizes it
+    #[default]
+    Minimize,
+    // Focus the newest window; clicking again focuses the next one
+    Cycle,
+    // Always just focus the newest window (macOS behaviour)
+    Activate,
+}
+
+// What an item stands for. Folders, links and the trash are always pinned, since nothing runs for them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ItemKind {
+    #[default]
+    App,
+    // Opens in the file manager
+    Folder(PathBuf),
+    // A web page or other URL, opened with its default handler
+    Link(String),
+    // The user's trash; files dropped on it are moved there
+    Trash,
+}
+
+const TRASH_ID: &str = "trash:///";
+
+// Something with an icon on the shelf. Pinned items stay put; unpinned items exist
+// only while one of their windows is open and live in the right-hand section.
+#[derive(Debug, Clone, Default)]
+pub struct DockItem {
+    pub kind: ItemKind,
+    pub desktop_id: Option<String>,
+    // app_id of the window that created an unpinned item with no desktop entry
+    pub app_id: Option<String>,
+    pub name: String,
+    pub icon: Option<String>,
+    pub exec: Option<String>,
+    pub startup_wm_class: Option<String>,
+    // Set for a desktop file outside the XDG data dirs, which the index can't find by id
+    pub desktop_file: Option<PathBuf>,
+    // Jump list entries from the desktop file, with names in the user's language
+    pub actions: Vec<DesktopAction>,
+    // File types the app says it opens, from MimeType=
+    pub mime_types: Vec<String>,
+    // How a folder's stack opens
+    pub stack: StackOptions,
+    pub pinned: bool,
+    // What config.toml replaces for this item; icon/exec/name above already have these applied
+    pub overrides: ItemOverrides,
+    // Number of tracked toplevels belonging to this item
+    pub running: usize,
+}
+
+impl DockItem {
+    pub fn from_entry(entry: &DesktopEntry, pinned: bool) -> DockItem {
+        DockItem {
+            kind: ItemKind::App,
+            desktop_id: Some(entry.id.clone()),
+            app_id: None,
+            name: entry.name.clone(),
+            icon: entry.icon.clone(),
+            exec: entry.exec.clone(),
+            startup_wm_class: entry.startup_wm_class.clone(),
+            desktop_file: None,
+            actions: entry.actions.clone(),
+            mime_types: entry.mime_types.clone(),
+            stack: StackOptions::default(),
+            pinned,
+            overrides: ItemOverrides::default(),
+            running: 0,
+        }
+    }
+
+    pub fn from_pinned(pinned: &PinnedItem, index: &DesktopIndex) -> DockItem {
+        let entry = match Path::new(&pinned.id).is_absolute() {
+            true => crate::desktop::parse(Path::new(&pinned.id))
+                .map_err(|e| eprintln!("{}:{} {}", file!(), line!(), e))
+                .ok()
+                .map(|entry| DockItem { desktop_file: Some(entry.path.clone()), ..DockItem::from_entry(&entry, true) }),
+            false => index.by_id(&pinned.id).map(|entry| DockItem::from_entry(entry, true)),
+        };
+        let mut item = match (pinned.folder.as_ref(), pinned.url.as_ref(), entry) {
+            _ if pinned.trash => DockItem::for_trash(),
+            (Some(folder), _, _) => DockItem {
+                stack: StackOptions { view: pinned.view.unwrap_or_default(), sort: pinned.sort.unwrap_or_default() },
+                ..DockItem::for_folder(folder)
+            },
+            (None, Some(url), _) => DockItem::for_link(url),
+            (None, None, Some(item)) => item,
+            (None, None, None) => {
+                eprintln!("{}:{} no desktop entry for pinned item {:?}", file!(), line!(), pinned.id);
+                DockItem {
+                    desktop_id: Some(pinned.id.clone()),
+                    name: pinned.id.clone(),
+                    icon: Some(pinned.id.clone()),
+                    pinned: true,
+                    ..Default::default()
+                }
+            }
+        };
+        if let Some(icon) = pinned.overrides.icon.as_ref() {
+            item.icon = Some(icon.clone());
+        }
+        if let Some(command) = pinned.overrides.command.as_ref() {
+            item.exec = Some(command.clone());
+        }
+        if let Some(label) = pinned.overrides.label.as_ref() {
+            item.name = label.clone();
+        }
+        item.overrides = pinned.overrides.clone();
+        item
+    }
+
+    pub fn to_pinned(&self) -> PinnedItem {
+        let mut pinned = PinnedItem { overrides: self.overrides.clone(), ..Default::default() };
+        match &self.kind {
+            ItemKind::App => {
+                pinned.id = match self.desktop_file.as_ref() {
+                    Some(path) => path.to_string_lossy().to_string(),
+                    None => self.desktop_id.clone().or_else(|| self.app_id.clone()).unwrap_or_default(),
+                };
+            }
+            ItemKind::Folder(path) => {
+                pinned.folder = Some(path.clone());
+                // Defaults are left out to keep the config short
+                pinned.view = Some(self.stack.view).filter(|v| *v != StackView::default());
+                pinned.sort = Some(self.stack.sort).filter(|s| *s != StackSort::default());
+            }
+            ItemKind::Link(url) => pinned.url = Some(url.clone()),
+            ItemKind::Trash => pinned.trash = true,
+        }
+        pinned
+    }
+
+    pub fn for_folder(path: &Path) -> DockItem {
+        let is_home = std::env::var_os("HOME").is_some_and(|home| Path::new(&home) == path);
+        DockItem {
+            kind: ItemKind::Folder(path.to_path_buf()),
+            name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| path.to_string_lossy().to_string()),
+            icon: Some(if is_home { "user-home" } else { "folder" }.to_string()),
+            pinned: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn for_link(url: &str) -> DockItem {
+        // "https://example.org/page" -> "example.org"
+        let host = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url).split('/').next().unwrap_or(url);
+        DockItem {
+            kind: ItemKind::Link(url.to_string()),
+            name: host.trim_start_matches("www.").to_string(),
+            icon: Some("web-browser".to_string()),
+            pinned: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn for_trash() -> DockItem {
+        DockItem {
+            kind: ItemKind::Trash,
+            name: "Trash".to_string(),
+            icon: Some(trash_icon(crate::trash::is_empty()).to_string()),
+            pinned: true,
+            ..Default::default()
+        }
+    }
+
+    // What dropping a URI on the shelf adds: an app for a .desktop file, a folder, or a link.
+    // Other files are left alone; they need an app to open them.
+    pub fn from_uri(uri: &str, index: &DesktopIndex) -> Option<DockItem> {
+        let path = match crate::desktop::uri_to_path(uri) {
+            Some(path) => path,
+            None if uri.starts_with("file:") || !uri.contains(':') => return None,
+            None => return Some(DockItem::for_link(uri)),
+        };
+        if path.is_dir() {
+            return Some(DockItem::for_folder(&path));
+        }
+        if path.extension().is_none_or(|ext| ext != "desktop") {
+            return None;
+        }
+        let entry = crate::desktop::parse(&path).map_err(|e| eprintln!("{}:{} {}", file!(), line!(), e)).ok()?;
+        // One the index knows is saved by id, so it follows package updates
+        match index.by_id(&entry.id) {
+            Some(indexed) if indexed.path == path => Some(DockItem::from_entry(indexed, true)),
+            _ => Some(DockItem { desktop_file: Some(path), ..DockItem::from_entry(&entry, true) }),
+        }
+    }
+
+    pub fn for_app_id(app_id: &str) -> DockItem {
+        DockItem {
+            app_id: Some(app_id.to_string()),
+            name: app_id.to_string(),
+            icon: Some(app_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    // Stable name for this item: its desktop file id, else the app_id it was created for;
+    // the path or URL for folders and links, and trash:/// for the trash
+    pub fn id(&self) -> &str {
+        match &self.kind {
+            ItemKind::App => self.desktop_id.as_deref().or(self.app_id.as_deref()).unwrap_or(&self.name),
+            ItemKind::Folder(path) => path.to_str().unwrap_or(&self.name),
+            ItemKind::Link(url) => url,
+            ItemKind::Trash => TRASH_ID,
+        }
+    }
+
+    pub fn matches_app_id(&self, app_id: &str) -> bool {
+        if let Some(desktop_id) = self.desktop_id.as_ref() && crate::desktop::app_id_matches(desktop_id, self.startup_wm_class.as_deref(), app_id) {
+            return true;
+        }
+        self.app_id.as_deref() == Some(app_id)
+    }
+
+    pub fn launch(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match (&self.kind, self.exec.as_ref()) {
+            (ItemKind::Folder(path), _) => crate::desktop::spawn_argv(&["xdg-open".to_string(), path.to_string_lossy().to_string()]),
+            (ItemKind::Link(url), _) => crate::desktop::spawn_argv(&["xdg-open".to_string(), url.clone()]),
+            // The folder rather than trash:///, which only file managers built on GVfs understand
+            (ItemKind::Trash, _) => {
+                crate::trash::create_dirs()?;
+                crate::desktop::spawn_argv(&["xdg-open".to_string(), crate::trash::files_dir().to_string_lossy().to_string()])
+            }
+            (ItemKind::App, Some(exec)) => crate::desktop::spawn_argv(&crate::desktop::exec_argv(exec)),
+            (ItemKind::App, None) => Err(format!("{} has no Exec= line to launch", self.name).into()),
+        }
+    }
+
+    // Start the app on some documents, as many times as its Exec= line needs
+    pub fn launch_with(&self, uris: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let exec = self.exec.as_ref().ok_or_else(|| format!("{} has no Exec= line to launch", self.name))?;
+        if !crate::desktop::exec_accepts_files(exec) {
+            return Err(format!("{} cannot be given files to open", self.name).into());
+        }
+        for argv in crate::desktop::expand_exec(exec, uris) {
+            crate::desktop::spawn_argv(&argv)?;
+        }
+        Ok(())
+    }
+
+    // Would launch_with open every one of these types? An app that lists no types in
+    // MimeType= takes none, even if its Exec= line has a field code for files.
+    // The trash takes any local files, whatever their type.
+    pub fn accepts(&self, mime_types: &[String], db: &MimeDb) -> bool {
+        if self.kind == ItemKind::Trash {
+            return !mime_types.is_empty() && !mime_types.iter().any(|m| m.starts_with("x-scheme-handler/"));
+        }
+        let takes_files = self.exec.as_deref().map(crate::desktop::exec_accepts_files).unwrap_or(false);
+        takes_files && !mime_types.is_empty()
+            && mime_types.iter().all(|m| self.mime_types.iter().any(|wanted| db.is_a(m, wanted)))
+    }
+
+    pub fn launch_action(&self, action_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let action = self.actions.iter().find(|a| a.id == action_id).ok_or_else(|| {
+            let ids: Vec<&str> = self.actions.iter().map(|a| a.id.as_str()).collect();
+            format!("{} has no action {:?} (it has: {})", self.name, action_id, ids.join(", "))
+        })?;
+        match action.exec.as_ref() {
+            Some(exec) => crate::desktop::spawn_argv(&crate::desktop::exec_argv(exec)),
+            None => Err(format!("{} has no Exec= line to launch", action.name).into()),
+        }
+    }
+
+    // This item's windows, oldest first (the order the compositor announced them)
+    pub fn windows<'a>(&self, toplevels: &'a Toplevels) -> Vec<&'a Toplevel> {
+        toplevels.visible().filter(|t| self.matches_app_id(&t.app_id)).collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Dock {
+    pub items: Vec<DockItem>,
+}
+
+impl Dock {
+    pub fn from_config(pinned: &[PinnedItem], index: &DesktopIndex) -> Dock {
+        Dock {
+            items: pinned.iter().map(|p| DockItem::from_pinned(p, index)).collect(),
+        }
+    }
+
+    pub fn pinned(&self) -> Vec<PinnedItem> {
+        self.items.iter().filter(|i| i.pinned).map(|i| i.to_pinned()).collect()
+    }
+
+    // Keeps pinned items ahead of running-only ones by moving the item to the end of the pinned section
+    pub fn pin(&mut self, i: usize) {
+        if i >= self.items.len() || self.items[i].pinned {
+            return;
+        }
+        let mut item = self.items.remove(i);
+        item.pinned = true;
+        let insert_at = self.items.iter().take_while(|i| i.pinned).count();
+        self.items.insert(insert_at, item);
+    }
+
+    // A running item stays on the dock, in the running section, until its windows close
+    pub fn unpin(&mut self, i: usize) {
+        if i >= self.items.len() || !self.items[i].pinned {
+            return;
+        }
+        let mut item = self.items.remove(i);
+        if item.running > 0 {
+            item.pinned = false;
+            self.items.push(item);
+        }
+    }
+
+    // Pin a dropped-in item at `at` in the pinned section, or move the one already on the
+    // dock there if it has the same id. Returns where it ended up.
+    pub fn add(&mut self, mut item: DockItem, at: usize) -> usize {
+        let pinned_count = self.items.iter().take_while(|i| i.pinned).count();
+        let at = at.min(pinned_count);
+        match self.items.iter().position(|i| i.id() == item.id()) {
+            Some(mut from) => {
+                if !self.items[from].pinned {
+                    self.pin(from);
+                    from = pinned_count;
+                }
+                // `at` counts this item too, so moving it down the dock lands one earlier
+                let to = if from < at { at - 1 } else { at };
+                self.move_item(from, to);
+                to
+            }
+            None => {
+                item.pinned = true;
+                self.items.insert(at, item);
+                at
+            }
+        }
+    }
+
+    // Moves within the item's own section only
+    pub fn move_item(&mut self, from: usize, to: usize) {
+        if from >= self.items.len() {
+            return;
+        }
+        let pinned = self.items[from].pinned;
+        let section_start = if pinned { 0 } else { self.items.iter().take_while(|i| i.pinned).count() };
+        let section_end = if pinned { self.items.iter().take_while(|i| i.pinned).count() } else { self.items.len() };
+        let to = to.clamp(section_start, section_end.saturating_sub(1).max(section_start));
+        let item = self.items.remove(from);
+        self.items.insert(to, item);
+    }
+
+    // Show the trash as empty or full; true if its icon changed
+    pub fn set_trash_full(&mut self, full: bool) -> bool {
+        let mut changed = false;
+        for item in self.items.iter_mut().filter(|i| i.kind == ItemKind::Trash && i.overrides.icon.is_none()) {
+            let icon = Some(trash_icon(!full).to_string());
+            changed |= item.icon != icon;
+            item.icon = icon;
+        }
+        changed
+    }
+
+    // Recount windows per item, add items for newly seen apps and drop unpinned items
+    // whose last window closed. Returns true if anything visible changed.
+    pub fn sync_running<'a>(&mut self, toplevels: impl Iterator<Item = &'a Toplevel>, index: &DesktopIndex) -> bool {
+        let before: Vec<(Option<String>, Option<String>, usize)> = self.items.iter()
+            .map(|i| (i.desktop_id.clone(), i.app_id.clone(), i.running))
+            .collect();
+
+        for item in self.items.iter_mut() {
+            item.running = 0;
+        }
+        for toplevel in toplevels {
+            match self.items.iter_mut().find(|i| i.matches_app_id(&toplevel.app_id)) {
+                Some(item) => {
+                    item.running += 1;
+                }
+                None => {
+                    let mut item = match index.for_app_id(&toplevel.app_id) {
+                        Some(entry) => DockItem::from_entry(entry, false),
+                        None => DockItem::for_app_id(&toplevel.app_id),
+                    };
+                    item.running = 1;
+                    self.items.push(item);
+                }
+            }
+        }
+        self.items.retain(|i| i.pinned || i.running > 0);
+
+        let after: Vec<(Option<String>, Option<String>, usize)> = self.items.iter()
+            .map(|i| (i.desktop_id.clone(), i.app_id.clone(), i.running))
+            .collect();
+        before != after
+    }
+}
+
+fn trash_icon(empty: bool) -> &'static str {
+    if empty { "user-trash" } else { "user-trash-full" }
+}
+
+// Icons + running indicators, drawn over the finished shelf (and its desktop reflection).
+// Back to front: contact shadows, icon reflections, icons, indicators.
+pub fn draw_items(canvas: &mut Canvas, items: &[DockItem], layout: &DockLayout, icons: &mut IconCache, theme: &Theme) {
+    if let Some(sep) = layout.separator {
+        // A light line with a dark one beside it, along the direction items run in
+        let (shade_dx, shade_dy) = if layout.edge.is_vertical() { (0, 1) } else { (1, 0) };
+        for y in sep.y..(sep.y + sep.h) {
+            for x in sep.x..(sep.x + sep.w) {
+                canvas.blend(x, y, [0xFF, 0xFF, 0xFF, 0xFF], 0.35);
+                canvas.blend(x + shade_dx, y + shade_dy, [0x00, 0x00, 0x00, 0xFF], 0.25);
+            }
+        }
+    }
+
+    // A dragged item is drawn in its own popup under the pointer instead
+    let on_shelf = |i: &usize| layout.dragged != Some(*i);
+
+    for (i, item) in items.iter().enumerate().filter(|(i, _)| on_shelf(i)) {
+        let (rect, lift) = match (layout.icons.get(i), layout.lifts.get(i)) {
+            (Some(rect), Some(lift)) => (*rect, *lift),
+            _ => continue,
+        };
+        draw_contact_shadow(canvas, rect, lift, layout.edge, theme);
+        // Tiger's flat dock has nothing shiny to reflect in
+        if layout.style == DockStyle::Shelf && let Some(img) = item.icon.as_ref().and_then(|name| icons.get(name, rect.w as u32)) {
+            draw_icon_reflection(canvas, img, rect, lift, layout.edge, theme);
+        }
+    }
+
+    for (i, item) in items.iter().enumerate().filter(|(i, _)| on_shelf(i)) {
+        let rect = match layout.icons.get(i) {
+            Some(rect) => *rect,
+            None => continue,
+        };
+        let icon = item.icon.as_ref().and_then(|name| icons.get(name, rect.w as u32));
+        match icon {
+            Some(img) => {
+                canvas.blit(img, rect.x, rect.y, 1.0);
+            }
+            None => {
+                // No PNG found; draw a neutral tile so the item is still clickable
+                let inset = rect.w / 10;
+                canvas.fill_rounded_rect(rect.x + inset, rect.y + inset, rect.w - (2 * inset), rect.h - (2 * inset), rect.w / 5, [0x70, 0x70, 0x70, 0xE0]);
+            }
+        }
+        // Darkened, like a pressed button, while files dropped here would open with it
+        if layout.drop_highlight == Some(i) {
+            canvas.fill_rounded_rect(rect.x, rect.y, rect.w, rect.h, rect.w / 5, [0x00, 0x00, 0x00, 0x70]);
+        }
+    }
+
+    for (i, item) in items.iter().enumerate().filter(|(i, _)| on_shelf(i)) {
+        if item.running > 0 && let (Some(rect), Some(&(cx, cy))) = (layout.icons.get(i), layout.indicators.get(i)) {
+            draw_indicator(canvas, cx, cy, rect.w as f32, theme);
+        }
+    }
+}
+
+// Rounded dark pill with the item's name, sized to fit; shown in a tooltip popup
+pub fn label_image(text: &TextRenderer, label: &str, theme: &Theme) -> Image {
+    let style = &theme.label;
+    let rendered = text.render(label, style.size_px, style.color.bgra_premul());
+    let pad = style.padding_px as i32;
+    let (box_w, box_h) = (rendered.w as i32 + (2 * pad), rendered.h as i32 + pad);
+    let mut img = Image::new(box_w as u32, box_h as u32);
+    let mut canvas = Canvas::new(&mut img.px, box_w as u32, box_h as u32);
+    canvas.fill_rounded_rect(0, 0, box_w, box_h, box_h / 2, style.background.bgra_premul());
+    canvas.blit(&rendered, pad, pad / 2, 1.0);
+    img
+}
+
+// Screen position of a point on the shelf floor under an icon: `along` pixels along the dock
+// from the icon's start and `toward_edge` pixels past its (un-bounced) base, toward the screen edge
+fn floor_point(rect: Rect, lift: i32, edge: Edge, along: i32, toward_edge: i32) -> (i32, i32) {
+    match edge {
+        Edge::Bottom => (rect.x + along, rect.y + rect.h + lift + toward_edge),
+        Edge::Top => (rect.x + along, rect.y - lift - 1 - toward_edge),
+        Edge::Left => (rect.x - lift - 1 - toward_edge, rect.y + along),
+        Edge::Right => (rect.x + rect.w + lift + toward_edge, rect.y + along),
+    }
+}
+
+// The icon mirrored about the floor it stands on. A bouncing icon's reflection drops away
+// from it by the same amount, like a real mirror.
+fn draw_icon_reflection(canvas: &mut Canvas, img: &Image, rect: Rect, lift: i32, edge: Edge, theme: &Theme) {
+    let floor = &theme.floor;
+    let size = img.w.min(img.h) as i32;
+    let depth = ((size + lift) as f32 * floor.reflection_squash).ceil() as i32;
+    for v in 0..depth {
+        // Height above the floor this row of the reflection mirrors
+        let height = ((v as f32 + 0.5) / floor.reflection_squash) as i32 - lift;
+        if height < 0 || height >= size {
+            continue;
+        }
+        let opacity = floor.reflection_opacity * (1.0 - (v as f32 / depth as f32));
+        for u in 0..size {
+            // Image pixel at `height` above the floor, `u` along the dock
+            let (ix, iy) = match edge {
+                Edge::Bottom => (u, size - 1 - height),
+                Edge::Top => (u, height),
+                Edge::Left => (height, u),
+                Edge::Right => (size - 1 - height, u),
+            };
+            let (x, y) = floor_point(rect, lift, edge, u, v);
+            canvas.blend(x, y, img.px[((iy * img.w as i32) + ix) as usize], opacity);
+        }
+    }
+}
+
+// Soft elliptical shadow where the icon meets the shelf, fainter the higher the icon bounces
+fn draw_contact_shadow(canvas: &mut Canvas, rect: Rect, lift: i32, edge: Edge, theme: &Theme) {
+    let floor = &theme.floor;
+    let size = rect.w.min(rect.h) as f32;
+    let radius_along = (size * floor.shadow_width / 2.0).max(1.0);
+    let radius_depth = (size * floor.shadow_depth).max(1.0);
+    let opacity = floor.shadow_opacity * (1.0 - (lift as f32 / size)).clamp(0.0, 1.0);
+    if opacity <= 0.0 {
+        return;
+    }
+    let center = size / 2.0;
+    for v in -(radius_depth.ceil() as i32)..=(radius_depth.ceil() as i32) {
+        for u in (center - radius_along).floor() as i32..=(center + radius_along).ceil() as i32 {
+            let du = (u as f32 + 0.5 - center) / radius_along;
+            let dv = (v as f32 + 0.5) / radius_depth;
+            let dist = ((du * du) + (dv * dv)).sqrt();
+            if dist >= 1.0 {
+                continue;
+            }
+            // Measured from the floor, so the shadow stays put while the icon bounces
+            let (x, y) = floor_point(rect, lift, edge, u, v);
+            canvas.blend(x, y, [0x00, 0x00, 0x00, 0xFF], opacity * (1.0 - dist).powi(2));
+        }
+    }
+}
+
+// The Leopard-era glow under running apps: a soft halo around a bright core
+fn draw_indicator(canvas: &mut Canvas, cx: f32, cy: f32, icon_size: f32, theme: &Theme) {
+    if let Some(img) = theme.skin_images.as_ref().and_then(|skin| skin.indicator.as_ref()) {
+        canvas.blit(img, cx as i32 - (img.w as i32 / 2), cy as i32 - (img.h as i32 / 2), 1.0);
+        return;
+    }
+    let indicator = &theme.indicator;
+    let radius = (icon_size * indicator.radius).max(4.0);
+    canvas.glow(cx, cy, radius, indicator.color.bgr(), indicator.intensity);
+    canvas.glow(cx, cy, radius / 3.0, indicator.core_color.bgr(), 1.0);
+}
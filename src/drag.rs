@@ -0,0 +1,113 @@
+
+use crate::canvas::{Canvas, Image};
+use crate::dock::DockItem;
+use crate::layout::{DockLayout, DropSlot};
+use crate::popup::PopupId;
+
+// Rearranging the dock by dragging icons around it, and off it to remove them
+
+// How far the pointer has to move with the button down before a press becomes a drag
+pub const DRAG_THRESHOLD_PX: f64 = 6.0;
+
+// A left press on an item; it becomes a drag once the pointer moves far enough
+pub struct Drag {
+    // DockItem::id() of the item, since indexes shift as windows come and go
+    pub item_id: String,
+    pub press: (f64, f64),
+    pub pointer: (f64, f64),
+    pub active: bool,
+    // Where it would land if dropped now; None = off the shelf, where pinned items are removed
+    pub target: Option<DropSlot>,
+    // The target is off the shelf and the item is pinned, so dropping removes it
+    pub removing: bool,
+    // Every gap still open or closing, including the target's
+    pub gaps: Vec<DropSlot>,
+    // The icon under the pointer, in a popup so it can leave the dock surface
+    pub popup: Option<PopupId>,
+}
+
+impl Drag {
+    pub fn new(item_id: &str, press: (f64, f64)) -> Drag {
+        Drag { item_id: item_id.to_string(), press, pointer: press, active: false, target: None, removing: false, gaps: vec![], popup: None }
+    }
+
+    pub fn past_threshold(&self) -> bool {
+        let (dx, dy) = (self.pointer.0 - self.press.0, self.pointer.1 - self.press.1);
+        ((dx * dx) + (dy * dy)).sqrt() >= DRAG_THRESHOLD_PX
+    }
+}
+
+// Where the dragged item would land with the pointer at `pointer` (surface coordinates); None
+// for something dragged in from another client. More than an icon's height above the shelf,
+// or past either end of it, is off the dock.
+pub fn drop_target(layout: &DockLayout, items: &[DockItem], dragged: Option<usize>, pointer: (f64, f64)) -> Option<DropSlot> {
+    let edge = layout.edge;
+    let space_h = layout.dock_space.1 as i32;
+    let along = edge.along_dock(pointer);
+    let slack = layout.icon_size as f32;
+    if edge.depth_from_edge(pointer, space_h) > layout.band_h as f32 + slack
+        || along < layout.dock_x as f32 - slack
+        || along > (layout.dock_x + layout.dock_w) as f32 + slack {
+        return None;
+    }
+
+    // Count the other items whose middle is before the pointer
+    let center = |j: usize| {
+        let r = layout.icons[j];
+        edge.along_dock(((r.x as f64) + (r.w as f64 / 2.0), (r.y as f64) + (r.h as f64 / 2.0)))
+    };
+    let others = || (0..items.len()).filter(|j| Some(*j) != dragged && *j < layout.icons.len());
+    let index = others().filter(|j| center(*j) < along).count();
+    let pinned_before = others().filter(|j| items[*j].pinned).count();
+
+    // Pinned items stay pinned, and dragging one past the separator just parks it at the end.
+    // Anything new is pinned too.
+    if dragged.is_none_or(|i| items.get(i).is_none_or(|item| item.pinned)) {
+        return Some(DropSlot { index: index.min(pinned_before), pinned: true });
+    }
+    // Running items join the pinned section when dropped into it
+    let pinned = match index.cmp(&pinned_before) {
+        std::cmp::Ordering::Less => true,
+        std::cmp::Ordering::Greater => false,
+        std::cmp::Ordering::Equal => match layout.separator {
+            Some(sep) => pinned_before > 0 && along < edge.along_dock(((sep.x as f64) + (sep.w as f64 / 2.0), (sep.y as f64) + (sep.h as f64 / 2.0))),
+            None => false,
+        },
+    };
+    Some(DropSlot { index, pinned })
+}
+
+// The dragged icon, faded while it is somewhere dropping would remove it
+pub fn icon_image(icon: Option<&Image>, size: u32, removing: bool) -> Image {
+    let mut img = Image::new(size, size);
+    let mut canvas = Canvas::new(&mut img.px, size, size);
+    let opacity = if removing { 0.5 } else { 0.9 };
+    match icon {
+        Some(icon) => canvas.blit(icon, 0, 0, opacity),
+        None => {
+            let (inset, s) = ((size / 10) as i32, size as i32);
+            let tile = [(0x70 as f32 * opacity) as u8, (0x70 as f32 * opacity) as u8, (0x70 as f32 * opacity) as u8, (0xE0 as f32 * opacity) as u8];
+            canvas.fill_rounded_rect(inset, inset, s - (2 * inset), s - (2 * inset), s / 5, tile);
+        }
+    }
+    img
+}
+
+// The classic puff of smoke where a removed item vanishes; t runs 0.0 ..= 1.0
+pub fn poof_image(t: f32, size: u32) -> Image {
+    let mut img = Image::new(size, size);
+    let mut canvas = Canvas::new(&mut img.px, size, size);
+    let s = size as f32;
+    let (cx, cy) = (s / 2.0, s / 2.0);
+    let fade = (1.0 - t).powf(1.5);
+    // Puffs billow outward and swell as they thin out
+    const PUFFS: usize = 7;
+    for k in 0..PUFFS {
+        let angle = (k as f32 / PUFFS as f32) * std::f32::consts::TAU + 0.4;
+        let dist = s * (0.08 + (0.24 * t));
+        let radius = s * (0.16 + (0.16 * t));
+        canvas.glow(cx + (angle.cos() * dist), cy + (angle.sin() * dist), radius, [0xF0, 0xF0, 0xF0], 0.9 * fade);
+    }
+    canvas.glow(cx, cy, s * (0.2 + (0.15 * t)), [0xFF, 0xFF, 0xFF], fade);
+    img
+}
@@ -0,0 +1,109 @@
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::canvas::Image;
+
+// Only PNG icons are supported; SVG-only apps get the placeholder tile.
+const ICON_THEMES: [&str; 3] = ["hicolor", "Adwaita", "locolor"];
+const ICON_SIZES: [u32; 10] = [512, 256, 192, 128, 96, 72, 64, 48, 32, 24];
+
+pub fn lookup_icon_path(name: &str) -> Option<PathBuf> {
+    let as_path = Path::new(name);
+    if as_path.is_absolute() {
+        return if as_path.exists() { Some(as_path.to_path_buf()) } else { None };
+    }
+    let data_dirs = crate::desktop::data_dirs();
+    let mut icon_roots = vec![];
+    if let Some(home) = std::env::var_os("HOME") {
+        icon_roots.push(PathBuf::from(home).join(".icons"));
+    }
+    for data_dir in data_dirs.iter() {
+        icon_roots.push(data_dir.join("icons"));
+    }
+    // Largest first so scaling always goes down
+    for size in ICON_SIZES {
+        for root in icon_roots.iter() {
+            for theme in ICON_THEMES {
+                for category in ["apps", "places", "mimetypes", "status", "devices"] {
+                    let candidate = root.join(theme).join(format!("{size}x{size}")).join(category).join(format!("{name}.png"));
+                    if candidate.exists() {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+    }
+    for data_dir in data_dirs.iter() {
+        let candidate = data_dir.join("pixmaps").join(format!("{name}.png"));
+        if candidate.exists() {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+pub fn load_png(path: &Path) -> Result<Image, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    let mut raw = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut raw).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+
+    let mut img = Image::new(info.width, info.height);
+    let channels = info.color_type.samples();
+    for (i, px) in img.px.iter_mut().enumerate() {
+        let s = &raw[i * channels..(i + 1) * channels];
+        let (r, g, b, a) = match info.color_type {
+            png::ColorType::Grayscale => (s[0], s[0], s[0], 0xFF),
+            png::ColorType::GrayscaleAlpha => (s[0], s[0], s[0], s[1]),
+            png::ColorType::Rgb => (s[0], s[1], s[2], 0xFF),
+            png::ColorType::Rgba => (s[0], s[1], s[2], s[3]),
+            png::ColorType::Indexed => {
+                return Err(format!("{:?} is still indexed after expansion", path).into());
+            }
+        };
+        // Premultiply so the compositor and Canvas::blend agree
+        let premul = |c: u8| ((c as u32 * a as u32) / 255) as u8;
+        *px = [premul(b), premul(g), premul(r), a];
+    }
+    Ok(img)
+}
+
+// Icons are looked up by name once, then cached at every size the renderer has asked for.
+#[derive(Debug, Default)]
+pub struct IconCache {
+    originals: HashMap<String, Option<Image>>,
+    scaled: HashMap<(String, u32), Image>,
+}
+
+impl IconCache {
+    pub fn get(&mut self, name: &str, size: u32) -> Option<&Image> {
+        if size == 0 {
+            return None;
+        }
+        let key = (name.to_string(), size);
+        if !self.scaled.contains_key(&key) {
+            let original = self.originals.entry(name.to_string()).or_insert_with(|| {
+                let path = lookup_icon_path(name)?;
+                match load_png(&path) {
+                    Ok(img) => Some(img),
+                    Err(e) => {
+                        eprintln!("{}:{} {}", file!(), line!(), e);
+                        None
+                    }
+                }
+            });
+            let scaled = original.as_ref()?.scaled(size, size);
+            self.scaled.insert(key.clone(), scaled);
+        }
+        self.scaled.get(&key)
+    }
+
+    // Call when the icon theme or an item's custom icon may have changed on disk
+    pub fn clear(&mut self) {
+        self.originals.clear();
+        self.scaled.clear();
+    }
+}
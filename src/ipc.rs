@@ -0,0 +1,127 @@
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+// A tiny line protocol on a unix socket so scripts (and `sdock msg ...`) can poke the dock:
+//
+//   attention <app_id>        bounce the app's icon until one of its windows is focused
+//   action <app_id> <action>  run one of the app's desktop file actions, eg "new-private-window"
+//
+// Each connection sends one command line and gets back "ok" or "error: <why>".
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Attention(String),
+    Action { app_id: String, action: String },
+}
+
+impl std::str::FromStr for Command {
+    type Err = String;
+    fn from_str(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let verb = words.next().ok_or_else(|| "empty command".to_string())?;
+        let args: Vec<&str> = words.collect();
+        match (verb, args.as_slice()) {
+            ("attention", [app_id]) => Ok(Command::Attention(app_id.to_string())),
+            ("attention", _) => Err("usage: attention <app_id>".to_string()),
+            ("action", [app_id, action]) => Ok(Command::Action { app_id: app_id.to_string(), action: action.to_string() }),
+            ("action", _) => Err("usage: action <app_id> <action>".to_string()),
+            _ => Err(format!("unknown command {:?}", verb)),
+        }
+    }
+}
+
+pub fn socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) if !runtime_dir.is_empty() => PathBuf::from(runtime_dir).join("sdock.sock"),
+        _ => std::env::temp_dir().join(format!("sdock-{}.sock", nix::unistd::getuid())),
+    }
+}
+
+// One accepted connection, waiting for State to carry out its command and reply
+pub struct Request {
+    pub command: Result<Command, String>,
+    stream: UnixStream,
+}
+
+impl Request {
+    pub fn reply(mut self, result: Result<(), String>) {
+        let line = match result {
+            Ok(()) => "ok\n".to_string(),
+            Err(e) => format!("error: {}\n", e),
+        };
+        if let Err(e) = self.stream.write_all(line.as_bytes()) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+    }
+}
+
+pub struct Server {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Server {
+    pub fn bind() -> Result<Server, Box<dyn std::error::Error>> {
+        let path = socket_path();
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(format!("{:?} is in use, is another sdock running?", path).into());
+            }
+            // Left behind by a dock that crashed
+            std::fs::remove_file(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+        }
+        let listener = UnixListener::bind(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+        listener.set_nonblocking(true).map_err(crate::err::eloc!())?;
+        Ok(Server { listener, path })
+    }
+
+    // Every connection waiting to be accepted; clients send their line right away, so a
+    // short read timeout keeps a misbehaving one from stalling the dock.
+    pub fn accept(&self) -> Vec<Request> {
+        let mut requests = vec![];
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    eprintln!("{}:{} {}", file!(), line!(), e);
+                    break;
+                }
+            };
+            let mut line = String::new();
+            let read = stream.set_nonblocking(false)
+                .and_then(|_| stream.set_read_timeout(Some(std::time::Duration::from_millis(200))))
+                .and_then(|_| stream.try_clone())
+                .and_then(|reader| BufReader::new(reader).read_line(&mut line));
+            let command = match read {
+                Ok(_) => line.trim().parse::<Command>(),
+                Err(e) => Err(e.to_string()),
+            };
+            requests.push(Request { command, stream });
+        }
+        requests
+    }
+
+    pub fn as_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Client side, for `sdock msg <command...>`; returns the dock's reply line
+pub fn send(command: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    stream.write_all(format!("{}\n", command).as_bytes()).map_err(crate::err::eloc!())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply).map_err(crate::err::eloc!())?;
+    Ok(reply.trim_end().to_string())
+}
@@ -0,0 +1,434 @@
+
+use serde::{Deserialize, Serialize};
+
+use crate::dock::DockItem;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DockStyle {
+    // Leopard's angled glass shelf
+    #[default]
+    #[serde(rename = "3d")]
+    Shelf,
+    // Tiger's flat translucent rounded rectangle
+    #[serde(rename = "2d")]
+    Flat,
+}
+
+// Which side of the screen the dock sits against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    #[default]
+    Bottom,
+    Top,
+    Left,
+    Right,
+}
+
+// Every shelf renderer draws in "dock space": a bottom dock, with the screen edge along
+// the last row. Vertical docks are drawn there and then rotated onto the real buffer.
+impl Edge {
+    pub fn is_vertical(self) -> bool {
+        matches!(self, Edge::Left | Edge::Right)
+    }
+
+    // Size of the dock space holding a buf_w*buf_h screen-space buffer
+    pub fn dock_space_size(self, buf_w: u32, buf_h: u32) -> (u32, u32) {
+        if self.is_vertical() { (buf_h, buf_w) } else { (buf_w, buf_h) }
+    }
+
+    // Dock-space pixel -> screen-space pixel, for a dock space space_h rows tall
+    pub fn to_screen(self, x: i32, y: i32, space_h: i32) -> (i32, i32) {
+        match self {
+            Edge::Bottom => (x, y),
+            Edge::Top => (x, space_h - 1 - y),
+            Edge::Left => (space_h - 1 - y, x),
+            Edge::Right => (y, x),
+        }
+    }
+
+    pub fn rect_to_screen(self, r: Rect, space_h: i32) -> Rect {
+        match self {
+            Edge::Bottom => r,
+            Edge::Top => Rect { x: r.x, y: space_h - (r.y + r.h), w: r.w, h: r.h },
+            Edge::Left => Rect { x: space_h - (r.y + r.h), y: r.x, w: r.h, h: r.w },
+            Edge::Right => Rect { x: r.y, y: r.x, w: r.h, h: r.w },
+        }
+    }
+
+    // Screen-space unit step pointing from the screen edge into the screen
+    pub fn away_from_edge(self) -> (i32, i32) {
+        match self {
+            Edge::Bottom => (0, -1),
+            Edge::Top => (0, 1),
+            Edge::Left => (1, 0),
+            Edge::Right => (-1, 0),
+        }
+    }
+
+    // Screen-space pointer position -> position along the dock
+    pub fn along_dock(self, (x, y): (f64, f64)) -> f32 {
+        if self.is_vertical() { y as f32 } else { x as f32 }
+    }
+
+    // Screen-space pointer position -> distance from the screen edge, for a dock space space_h rows tall
+    pub fn depth_from_edge(self, (x, y): (f64, f64), space_h: i32) -> f32 {
+        match self {
+            Edge::Bottom => space_h as f32 - y as f32,
+            Edge::Top => y as f32,
+            Edge::Left => x as f32,
+            Edge::Right => space_h as f32 - x as f32,
+        }
+    }
+
+    pub fn point_to_screen(self, (x, y): (f32, f32), space_h: i32) -> (f32, f32) {
+        match self {
+            Edge::Bottom => (x, y),
+            Edge::Top => (x, space_h as f32 - y),
+            Edge::Left => (space_h as f32 - y, x),
+            Edge::Right => (y, x),
+        }
+    }
+
+    // Rotate a space_w*space_h dock-space image onto the screen
+    pub fn px_to_screen(self, px: &[[u8; 4]], space_w: u32, space_h: u32) -> Vec<[u8; 4]> {
+        if self == Edge::Bottom {
+            return px.to_vec();
+        }
+        let mut out = vec![[0, 0, 0, 0]; px.len()];
+        for y in 0..space_h as i32 {
+            for x in 0..space_w as i32 {
+                let (sx, sy) = self.to_screen(x, y, space_h as i32);
+                let screen_w = if self.is_vertical() { space_h } else { space_w } as i32;
+                out[((sy * screen_w) + sx) as usize] = px[((y * space_w as i32) + x) as usize];
+            }
+        }
+        out
+    }
+
+    // The inverse: bring a screen_w*screen_h capture (eg a screenshot) into dock space
+    pub fn px_to_dock_space(self, px: &[[u8; 4]], screen_w: u32, screen_h: u32) -> Vec<[u8; 4]> {
+        if self == Edge::Bottom {
+            return px.to_vec();
+        }
+        let (space_w, space_h) = self.dock_space_size(screen_w, screen_h);
+        let mut out = Vec::with_capacity(px.len());
+        for y in 0..space_h as i32 {
+            for x in 0..space_w as i32 {
+                let (sx, sy) = self.to_screen(x, y, space_h as i32);
+                out.push(px[((sy * screen_w as i32) + sx) as usize]);
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        x >= self.x as f64 && x < (self.x + self.w) as f64 && y >= self.y as f64 && y < (self.y + self.h) as f64
+    }
+}
+
+// Where everything lives on the dock surface for the current buffer size + item list.
+// Computed once per draw and kept around for pointer hit-testing.
+// icons, indicators and separator are in screen space; the shelf geometry is in dock space.
+#[derive(Debug, Clone, Default)]
+pub struct DockLayout {
+    pub buf_w: u32,
+    pub buf_h: u32,
+    pub edge: Edge,
+    pub style: DockStyle,
+    // (w, h) of the dock space the surface is drawn in
+    pub dock_space: (u32, u32),
+    // Depth of the shelf band along the screen edge; dock_x, dock_w and shelf are relative to it
+    pub band_h: u32,
+    pub dock_x: i32,
+    pub dock_w: i32,
+    // Visible body of the dock: the flat rectangle in 2D, the trapezoid's bounding box in 3D
+    pub shelf: Rect,
+    pub icon_size: i32,
+    pub icons: Vec<Rect>,
+    // Center of each item's running indicator
+    pub indicators: Vec<(f32, f32)>,
+    // How far each icon is raised off the shelf (eg mid-bounce), in pixels
+    pub lifts: Vec<i32>,
+    // Item under the pointer, which gets a name label
+    pub hovered: Option<usize>,
+    // Thin line dividing pinned and running-only items, across the dock
+    pub separator: Option<Rect>,
+    // Item being dragged; it has no slot (and a zero-sized rect) until dropped
+    pub dragged: Option<usize>,
+    // Item that would open the files being dragged over it from another app
+    pub drop_highlight: Option<usize>,
+}
+
+impl DockLayout {
+    // Raise icon i off the shelf, away from the screen edge; its indicator stays put
+    pub fn lift_icon(&mut self, i: usize, lift: i32) {
+        let (dx, dy) = self.edge.away_from_edge();
+        if let (Some(rect), Some(current)) = (self.icons.get_mut(i), self.lifts.get_mut(i)) {
+            rect.x += dx * (lift - *current);
+            rect.y += dy * (lift - *current);
+            *current = lift;
+        }
+    }
+
+    pub fn item_at(&self, x: f64, y: f64) -> Option<usize> {
+        self.icons.iter().position(|r| r.contains(x, y))
+    }
+}
+
+// Same (odd) trig static_draw has always used to slope the shelf sides
+pub fn shelf_top_x_inset(buf_y: u32, dock_angle_deg: i32) -> f32 {
+    let dock_top_x_inset = f32::sin(dock_angle_deg as f32 * (180.0 / std::f32::consts::PI)) * buf_y as f32;
+    dock_top_x_inset.abs()
+}
+
+// Everything besides the surface size and items that decides where things go
+#[derive(Debug, Clone)]
+pub struct LayoutParams {
+    pub edge: Edge,
+    pub style: DockStyle,
+    pub shadow_w_px: i32,
+    pub dock_angle_deg: i32,
+    // Surface depth / shelf depth; above 1.0 leaves headroom for magnified icons
+    pub surface_scale: f32,
+    pub magnify: Option<Magnify>,
+    pub drag: Option<DragParams>,
+}
+
+// An item lifted off the shelf: it takes no slot, and gaps open where it could land.
+// Things dragged in from other clients open gaps the same way, with no item lifted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DragParams {
+    pub item: Option<usize>,
+    // How far open each gap is, 0.0 ..= 1.0 of a slot
+    pub gaps: Vec<(DropSlot, f32)>,
+}
+
+// Somewhere a dragged item can land
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DropSlot {
+    // Position among the other items, ie the index it ends up at
+    pub index: usize,
+    // Which section it joins; only matters right at the separator
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Magnify {
+    // Pointer position along the dock, in dock space
+    pub pointer: f32,
+    pub max_scale: f32,
+    // How far the effect reaches either side of the pointer, in icon widths
+    pub range: f32,
+}
+
+// How high a bouncing icon jumps, as a fraction of its size
+pub const BOUNCE_HEIGHT: f32 = 0.5;
+
+// Roughly how much of the shelf's depth an icon takes up
+const ICON_DEPTH_FRACTION: f32 = 0.75;
+
+// How much deeper than the shelf the surface must be for icons to reach max_scale times their size
+pub fn surface_scale(max_scale: f32) -> f32 {
+    1.0 + ((max_scale - 1.0).max(0.0) * ICON_DEPTH_FRACTION)
+}
+
+// Rows of a space_h-deep dock space the shelf occupies; the rest is headroom above it
+pub fn band_height(space_h: u32, surface_scale: f32) -> u32 {
+    ((space_h as f32 / surface_scale.max(1.0)).round() as u32).clamp(1, space_h.max(1))
+}
+
+// Cosine bump: max_scale right under the pointer, back to 1.0 at `range_px` away
+fn magnification(dist: f32, range_px: f32, max_scale: f32) -> f32 {
+    if dist >= range_px {
+        return 1.0;
+    }
+    1.0 + ((max_scale - 1.0) * 0.5 * (1.0 + (std::f32::consts::PI * dist / range_px).cos()))
+}
+
+pub fn compute(screen_w: u32, screen_h: u32, items: &[DockItem], params: &LayoutParams) -> DockLayout {
+    let LayoutParams { edge, style, shadow_w_px, dock_angle_deg, surface_scale, magnify, ref drag } = *params;
+
+    // Lay out a bottom dock, then rotate the results for other edges
+    let (buf_w, space_h) = edge.dock_space_size(screen_w, screen_h);
+    // The shelf keeps to a band along the screen edge; only magnified icons reach above it
+    let buf_h = band_height(space_h, surface_scale);
+    let band_top = (space_h - buf_h) as i32;
+    let dock_w = (buf_w / 2) as i32;
+    let dock_x = ((buf_w as i32) - dock_w) / 2;
+
+    let mut layout = DockLayout {
+        buf_w: screen_w,
+        buf_h: screen_h,
+        edge,
+        style,
+        dock_space: (buf_w, space_h),
+        band_h: buf_h,
+        dock_x,
+        dock_w,
+        shelf: match style {
+            DockStyle::Shelf => Rect { x: dock_x, y: 0, w: dock_w, h: buf_h as i32 },
+            DockStyle::Flat => Rect { x: dock_x + shadow_w_px, y: shadow_w_px / 2, w: dock_w - (2 * shadow_w_px), h: buf_h as i32 - (shadow_w_px / 2) },
+        },
+        ..Default::default()
+    };
+    if items.is_empty() || buf_h < 12 {
+        return layout;
+    }
+
+    // Icons must fit between the sloped sides at the very top of the shelf
+    let top_inset = match style {
+        DockStyle::Shelf => shelf_top_x_inset(buf_h, dock_angle_deg) as i32,
+        DockStyle::Flat => 0,
+    };
+    let usable_w = (dock_w - (2 * (top_inset + shadow_w_px))).max(1);
+
+    let has_separator = items.iter().any(|i| i.pinned) && items.iter().any(|i| !i.pinned);
+    let indicator_band = ((buf_h as i32) / 6).max(8);
+
+    // Sized for every item, so picking one up doesn't rescale the rest
+    let n = items.len() as i32;
+    let slots = n + if has_separator { 1 } else { 0 };
+    let spacing_ratio = 8; // icon is 8x the gap between icons
+    let max_icon_from_w = (usable_w * spacing_ratio) / (slots * (spacing_ratio + 1));
+    let max_icon_from_h = ((buf_h as i32) - indicator_band - shadow_w_px).max(1);
+    let icon_size = max_icon_from_w.min(max_icon_from_h).max(1);
+    let gap = (icon_size / spacing_ratio).max(1);
+
+    let row = slot_row(items, has_separator, drag.as_ref());
+    // Every slot is one icon and one gap wide, times its weight; only drop gaps are partial
+    let weights: Vec<f32> = row.iter().map(|slot| match slot {
+        Slot::DropGap(open) => *open,
+        _ => 1.0,
+    }).collect();
+    let slot_pitch = (icon_size + gap) as f32;
+    let total_w = ((weights.iter().sum::<f32>() * slot_pitch) - gap as f32).round() as i32;
+    let row_x = dock_x + ((dock_w - total_w) / 2);
+    let icon_y = (buf_h as i32) - indicator_band - icon_size;
+    let icon_bottom = band_top + icon_y + icon_size;
+
+    // Slot sizes and positions along the dock; every slot is icon_size (times its weight) unless magnified
+    let mut sizes: Vec<i32> = weights.iter().map(|w| (w * icon_size as f32).round() as i32).collect();
+    let mut starts: Vec<f32> = Vec::with_capacity(row.len());
+    let mut x = row_x as f32;
+    for w in weights.iter() {
+        starts.push(x);
+        x += w * slot_pitch;
+    }
+    if let Some(m) = magnify && !row.is_empty() {
+        // Never grow past the top of the surface
+        let max_scale = m.max_scale.min(icon_bottom as f32 / icon_size as f32).max(1.0);
+        let range_px = (m.range * icon_size as f32).max(1.0);
+        for s in 0..row.len() {
+            let center = starts[s] + (weights[s] * icon_size as f32 / 2.0);
+            sizes[s] = (weights[s] * icon_size as f32 * magnification((m.pointer - center).abs(), range_px, max_scale)).round() as i32;
+        }
+        // Re-flow so the spot under the pointer stays put and neighbours are pushed outward
+        let mut flowed = Vec::with_capacity(sizes.len());
+        let mut x = 0.0;
+        for (size, w) in sizes.iter().zip(weights.iter()) {
+            flowed.push(x);
+            x += *size as f32 + (w * gap as f32);
+        }
+        // Past either end of the row, that end stays put instead
+        let pinned_at = m.pointer.clamp(row_x as f32, (row_x + total_w) as f32);
+        let k = starts.iter().rposition(|start| *start <= pinned_at).unwrap_or(0);
+        let t = ((pinned_at - starts[k]) / (weights[k] * slot_pitch).max(1.0)).clamp(0.0, 1.0);
+        let anchor = flowed[k] + (t * (sizes[k] as f32 + (weights[k] * gap as f32)));
+        let shift = pinned_at - anchor;
+        starts = flowed.into_iter().map(|x| x + shift).collect();
+    }
+
+    layout.icon_size = icon_size;
+    layout.icons = vec![Rect::default(); items.len()];
+    layout.indicators = vec![(0.0, 0.0); items.len()];
+    layout.lifts = vec![0; items.len()];
+    layout.dragged = drag.as_ref().and_then(|d| d.item);
+    for (s, slot) in row.iter().enumerate() {
+        let (x, size) = (starts[s].round() as i32, sizes[s]);
+        match *slot {
+            Slot::Separator => {
+                let separator = Rect { x: x + (size / 2), y: band_top + icon_y, w: 1, h: (buf_h as i32) - 2 - icon_y };
+                layout.separator = Some(edge.rect_to_screen(separator, space_h as i32));
+            }
+            Slot::Item(i) => {
+                // Icons grow away from the screen edge, standing on the same baseline
+                layout.icons[i] = edge.rect_to_screen(Rect { x, y: icon_bottom - size, w: size, h: size }, space_h as i32);
+                let indicator = (x as f32 + (size as f32 / 2.0), (space_h as i32 - (indicator_band / 2)) as f32);
+                layout.indicators[i] = edge.point_to_screen(indicator, space_h as i32);
+            }
+            Slot::DropGap(_) => { }
+        }
+    }
+
+    if style == DockStyle::Flat {
+        // Hug the icons, leaving the rest of the surface for the drop shadow
+        let top = (icon_y - (2 * gap)).max(shadow_w_px / 2);
+        layout.shelf.y = top;
+        layout.shelf.h = buf_h as i32 - top;
+    }
+
+    layout
+}
+
+enum Slot {
+    Item(usize),
+    Separator,
+    // Room opening (or closing) for a dragged item, by how open it is
+    DropGap(f32),
+}
+
+// Items in order with the separator between the sections, minus a dragged item, plus any
+// gaps opened for it. A gap at the section boundary sits on its own section's side.
+fn slot_row(items: &[DockItem], has_separator: bool, drag: Option<&DragParams>) -> Vec<Slot> {
+    let open = |index: usize, pinned: bool| -> f32 {
+        drag.map(|d| d.gaps.iter().filter(|(s, _)| s.index == index && s.pinned == pinned).map(|(_, open)| *open).sum::<f32>())
+            .unwrap_or(0.0)
+            .min(1.0)
+    };
+    let mut row = vec![];
+    let push_gap = |row: &mut Vec<Slot>, index: usize, pinned: bool| {
+        let open = open(index, pinned);
+        if open > 0.0 {
+            row.push(Slot::DropGap(open));
+        }
+    };
+    let mut index = 0; // Among the items that are not being dragged
+    let mut pinned_done = false;
+    let mut last_pinned = true;
+    for (i, item) in items.iter().enumerate() {
+        if drag.and_then(|d| d.item) == Some(i) {
+            continue;
+        }
+        // With nothing pinned there is no separator, but the pinned section's gap still goes first
+        if !item.pinned && !pinned_done {
+            push_gap(&mut row, index, true);
+            if has_separator {
+                row.push(Slot::Separator);
+            }
+            pinned_done = true;
+        }
+        push_gap(&mut row, index, item.pinned);
+        row.push(Slot::Item(i));
+        last_pinned = item.pinned;
+        index += 1;
+    }
+    // The dragged item was the only one in the running section
+    if !pinned_done && has_separator {
+        push_gap(&mut row, index, true);
+        row.push(Slot::Separator);
+        last_pinned = false;
+    }
+    push_gap(&mut row, index, last_pinned);
+    row
+}
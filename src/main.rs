@@ -6,20 +6,52 @@ use std::{fs::File, os::unix::io::AsFd};
 use wayland_client::{
     delegate_noop,
     protocol::{
-        wl_buffer, wl_compositor, wl_keyboard, wl_pointer, wl_registry, wl_seat, wl_shm, wl_shm_pool,
+        wl_buffer, wl_callback, wl_compositor, wl_keyboard, wl_pointer, wl_region, wl_registry, wl_seat, wl_shm, wl_shm_pool,
         wl_surface,
     },
     Connection, Dispatch, QueueHandle, WEnum,
 };
 
-use wayland_protocols::xdg::shell::client::{xdg_surface, xdg_toplevel, xdg_wm_base};
+use wayland_protocols::xdg::shell::client::{xdg_positioner, xdg_surface, xdg_toplevel, xdg_wm_base};
 
 // Our modules
 mod err;
 mod util;
+mod anim;
+mod canvas;
+mod config;
+mod desktop;
+mod dnd;
+mod dock;
+mod drag;
+mod icons;
+mod ipc;
+mod layout;
+mod menu;
+mod mime;
+mod popup;
+mod recent;
+mod theme;
+mod toplevel;
+mod trash;
+mod skin;
+mod stack;
+mod text;
+mod watch;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    std::thread::spawn(do_special_wm_configs);
+    // `sdock msg attention firefox` talks to the running dock instead of starting one
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(|a| a.as_str()) == Some("msg") {
+        let reply = ipc::send(&args[2..].join(" "))?;
+        println!("{}", reply);
+        std::process::exit(if reply == "ok" { 0 } else { 1 });
+    }
+
+    // Only the placement is needed this early; State::default() loads (and reports problems with) the config properly
+    let startup_config = config::Config::load().unwrap_or_default();
+    let (edge, surface_scale) = (startup_config.edge, startup_config.surface_scale());
+    std::thread::spawn(move || do_special_wm_configs(edge, surface_scale));
     std::thread::sleep(std::time::Duration::from_millis(20)); // Tiny delay to allow bg thread a chance to win race conditions
 
     let conn = Connection::connect_to_env().unwrap();
@@ -47,8 +79,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("Starting the example window app, press <ESC> to quit.");
 
+    state.watcher = match watch::Watcher::new() {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            eprintln!("{}:{} {}, config changes will need a restart", file!(), line!(), e);
+            None
+        }
+    };
+    if let Some(watcher) = state.watcher.as_mut() {
+        if let Err(e) = std::fs::create_dir_all(config::config_dir()) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+        if let Err(e) = watcher.watch_file(&config::config_path(), watch::WatchKind::Config) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+        if let Err(e) = watcher.watch_file(&recent::xbel_path(), watch::WatchKind::RecentFiles) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+        if let Err(e) = trash::create_dirs().and_then(|_| watcher.watch_dir(&trash::files_dir(), watch::WatchKind::Trash)) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+    }
+    state.watch_theme();
+
+    state.ipc = match ipc::Server::bind() {
+        Ok(server) => Some(server),
+        Err(e) => {
+            eprintln!("{}:{} {}, `sdock msg` will not work", file!(), line!(), e);
+            None
+        }
+    };
+
     while state.running {
-        event_queue.blocking_dispatch(&mut state).map_err(err::eloc!())?;
+        poll_dispatch(&mut event_queue, &mut state)?;
         // TODO determine based on window positions if drawing is appropriate; this loop runs _ALL_THE_TIME_
         state.take_screenshot(); // Queue error; libharuhi is also maintaining a connection; can we send ours to it so they can share?
         state.draw_from_stolen();
@@ -59,12 +122,82 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn do_special_wm_configs() {
+// Same as EventQueue::blocking_dispatch, but also wakes up when a watched file changes,
+// an IPC client connects or more of a dragged URI list arrives
+fn poll_dispatch(event_queue: &mut wayland_client::EventQueue<State>, state: &mut State) -> Result<(), Box<dyn std::error::Error>> {
+    use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+
+    event_queue.dispatch_pending(state).map_err(err::eloc!())?;
+    event_queue.flush().map_err(err::eloc!())?;
+
+    let mut watcher_readable = false;
+    let mut ipc_readable = false;
+    let mut dnd_readable = false;
+    if let Some(guard) = event_queue.prepare_read() {
+        let wayland_readable;
+        {
+            let mut fds = vec![PollFd::new(guard.connection_fd(), PollFlags::POLLIN)];
+            let watcher_i = state.watcher.as_ref().map(|watcher| {
+                fds.push(PollFd::new(watcher.as_fd(), PollFlags::POLLIN));
+                fds.len() - 1
+            });
+            let ipc_i = state.ipc.as_ref().map(|server| {
+                fds.push(PollFd::new(server.as_fd(), PollFlags::POLLIN));
+                fds.len() - 1
+            });
+            let dnd_i = state.dnd.receiving_fd().map(|fd| {
+                fds.push(PollFd::new(fd, PollFlags::POLLIN));
+                fds.len() - 1
+            });
+            poll(&mut fds, PollTimeout::NONE)
+                .or_else(|e| if e == nix::errno::Errno::EINTR { Ok(0) } else { Err(e) })
+                .map_err(err::eloc!())?;
+            let readable = PollFlags::POLLIN | PollFlags::POLLERR | PollFlags::POLLHUP;
+            let is_readable = |i: Option<usize>| i.and_then(|i| fds[i].revents()).map(|r| r.intersects(readable)).unwrap_or(false);
+            wayland_readable = is_readable(Some(0));
+            watcher_readable = is_readable(watcher_i);
+            ipc_readable = is_readable(ipc_i);
+            dnd_readable = is_readable(dnd_i);
+        }
+        if wayland_readable {
+            match guard.read() {
+                Err(wayland_client::backend::WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => { }
+                other => {
+                    other.map_err(err::eloc!())?;
+                }
+            }
+        }
+        // else: dropping the guard cancels the read
+    }
+
+    if watcher_readable {
+        state.on_watched_files_changed(&event_queue.handle());
+    }
+    if ipc_readable {
+        state.on_ipc_requests();
+    }
+    if dnd_readable {
+        state.on_dnd_readable();
+    }
+
+    event_queue.dispatch_pending(state).map_err(err::eloc!())?;
+    Ok(())
+}
+
+fn do_special_wm_configs(edge: layout::Edge, surface_scale: f32) {
     // Force sway to make the window float
     //std::thread::sleep(std::time::Duration::from_millis(300));
+    // Magnification makes the surface deeper; the shelf still sits in the same place along the edge
+    let depth = |ppt: f32| (ppt * surface_scale).round() as i32;
+    let (size, position) = match edge {
+        layout::Edge::Bottom => (format!("width 100ppt height {}ppt", depth(12.0)), format!("0 {}ppt", 101 - depth(12.0))),
+        layout::Edge::Top => (format!("width 100ppt height {}ppt", depth(12.0)), "0 0".to_string()),
+        layout::Edge::Left => (format!("width {}ppt height 100ppt", depth(7.0)), "0 0".to_string()),
+        layout::Edge::Right => (format!("width {}ppt height 100ppt", depth(7.0)), format!("{}ppt 0", 100 - depth(7.0))),
+    };
     let _s = std::process::Command::new("swaymsg")
-        // float, move resize to 100% by 12%, move to x=0, y=80% // sticky enable
-        .args(&["for_window [app_id=\"sdock\"] floating enable, for_window [app_id=\"sdock\"] resize set width 100ppt height 12ppt, for_window [app_id=\"sdock\"] move position 0 89ppt, for_window [app_id=\"sdock\"] sticky enable"])
+        // float, resize to the edge's strip, move against that edge // sticky enable
+        .args(&[format!("for_window [app_id=\"sdock\"] floating enable, for_window [app_id=\"sdock\"] resize set {}, for_window [app_id=\"sdock\"] move position {}, for_window [app_id=\"sdock\"] sticky enable", size, position)])
         .status();
 }
 
@@ -88,10 +221,62 @@ struct State {
     pub haruhi_shot: Result<libharuhishot::HaruhiShotState, libharuhishot::haruhierror::HaruhiError>,
     // Pixel values in format [b, g, r, a]
     pub last_screenshot_px: Vec::<[u8; 4]>,
+
+    pub seat: Option<wl_seat::WlSeat>,
+    pub toplevels: toplevel::Toplevels,
+    pub desktop_index: desktop::DesktopIndex,
+    pub recent: recent::RecentFiles,
+    pub mime: mime::MimeDb,
+    pub dock: dock::Dock,
+    pub icons: icons::IconCache,
+    pub layout: layout::DockLayout,
+
+    pub pointer_pos: Option<(f64, f64)>, // None while the pointer is outside our surface
+    pub pointer_popup: Option<(popup::PopupId, (f64, f64))>, // Which popup the pointer is over, and where
+    // Most recent button serial, for popup grabs
+    pub last_input_serial: u32,
+    pub scroll_accum: f64,
+
+    pub config: config::Config,
+    pub text: text::TextRenderer,
+    // config.edge and the magnification headroom as of startup; sway already sized and
+    // placed the surface for them, so edits wait for a restart
+    pub edge: layout::Edge,
+    pub surface_scale: f32,
+
+    pub ipc: Option<ipc::Server>,
+
+    pub popups: popup::Popups,
+    // The hovered item's name, shown in a popup above it
+    pub tooltip: Option<(String, popup::PopupId)>,
+    pub menu: Option<menu::Menu>,
+    // A folder item's open stack
+    pub stack: Option<stack::Stack>,
+    pub drag: Option<drag::Drag>,
+    // Files being dragged over the dock from other clients
+    pub dnd: dnd::DataDevice,
+    // The smoke popup left where a removed item was dropped, and its size
+    pub poof: Option<(popup::PopupId, u32)>,
+
+    pub anims: anim::Animations,
+    // A wl_surface.frame callback is outstanding; at most one is requested at a time
+    pub frame_pending: bool,
+    pub theme: theme::Theme,
+    pub watcher: Option<watch::Watcher>,
 }
 
 impl Default for State {
      fn default() -> State {
+        let config = config::Config::load().unwrap_or_else(|e| {
+            eprintln!("{}:{} {}, using the default config", file!(), line!(), e);
+            config::Config::default()
+        });
+        let theme = theme::Theme::load(&config.theme).unwrap_or_else(|e| {
+            eprintln!("{}:{} {}, using the bundled theme", file!(), line!(), e);
+            theme::Theme::leopard()
+        });
+        let desktop_index = desktop::DesktopIndex::load();
+        let dock = dock::Dock::from_config(&config.pinned, &desktop_index);
         State {
             running: true,
             base_surface: None,
@@ -106,6 +291,34 @@ impl Default for State {
             configured_w: 1,
             haruhi_shot: libharuhishot::HaruhiShotState::init(),
             last_screenshot_px: Vec::with_capacity(1920 * ((1200*80)/100) * 2), // Guess at a monitor size, take last 20% of space + double estimate. Yay heuristics for performance!
+            seat: None,
+            toplevels: toplevel::Toplevels::default(),
+            desktop_index,
+            recent: recent::RecentFiles::load(),
+            mime: mime::MimeDb::load(),
+            dock,
+            icons: icons::IconCache::default(),
+            layout: layout::DockLayout::default(),
+            pointer_pos: None,
+            scroll_accum: 0.0,
+            edge: config.edge,
+            surface_scale: config.surface_scale(),
+            ipc: None,
+            popups: popup::Popups::default(),
+            pointer_popup: None,
+            last_input_serial: 0,
+            tooltip: None,
+            menu: None,
+            stack: None,
+            drag: None,
+            dnd: dnd::DataDevice::default(),
+            poof: None,
+            anims: anim::Animations::default(),
+            frame_pending: false,
+            text: text::TextRenderer::load(&theme.label.font),
+            config,
+            theme,
+            watcher: None,
         }
     }
 }
@@ -123,7 +336,7 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
         state.stolen_registry = Some(registry.clone());
         state.stolen_qh = Some(qh.clone());
 
-        if let wl_registry::Event::Global { name, interface, .. } = event {
+        if let wl_registry::Event::Global { name, interface, version } = event {
             match &interface[..] {
                 "wl_compositor" => {
                     eprintln!("{}:{} got event name={} wl_compositor ", file!(), line!(), &name);
@@ -131,6 +344,7 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                         registry.bind::<wl_compositor::WlCompositor, _, _>(name, 1, qh, ());
                     let surface = compositor.create_surface(qh, ());
                     state.base_surface = Some(surface);
+                    state.popups.compositor = Some(compositor);
 
                     if state.wm_base.is_some() && state.xdg_surface.is_none() {
                         state.init_xdg_surface(qh);
@@ -138,21 +352,39 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                 }
                 "wl_shm" => {
                     eprintln!("{}:{} got event name={} wl_shm ", file!(), line!(), &name);
+                    state.popups.shm = Some(registry.bind::<wl_shm::WlShm, _, _>(name, 1, qh, ()));
                     state.draw(name, registry, qh);
                 }
                 "wl_seat" => {
                     eprintln!("{}:{} got event name={} wl_seat ", file!(), line!(), &name);
-                    registry.bind::<wl_seat::WlSeat, _, _>(name, 1, qh, ());
+                    let seat = registry.bind::<wl_seat::WlSeat, _, _>(name, 1, qh, ());
+                    state.seat = Some(seat);
+                    state.dnd.get_device(state.seat.as_ref(), qh);
                 }
                 "xdg_wm_base" => {
                     eprintln!("{}:{} got event name={} xdg_wm_base ", file!(), line!(), &name);
-                    let wm_base = registry.bind::<xdg_wm_base::XdgWmBase, _, _>(name, 1, qh, ());
+                    // v3 lets popups follow their icon with xdg_popup.reposition
+                    let wm_base = registry.bind::<xdg_wm_base::XdgWmBase, _, _>(name, version.min(3), qh, ());
+                    state.popups.wm_base = Some(wm_base.clone());
                     state.wm_base = Some(wm_base);
 
                     if state.base_surface.is_some() && state.xdg_surface.is_none() {
                         state.init_xdg_surface(qh);
                     }
                 }
+                "wl_data_device_manager" => {
+                    eprintln!("{}:{} got event name={} wl_data_device_manager ", file!(), line!(), &name);
+                    state.dnd.bind(registry, name, version, qh);
+                    state.dnd.get_device(state.seat.as_ref(), qh);
+                }
+                "zwlr_foreign_toplevel_manager_v1" => {
+                    eprintln!("{}:{} got event name={} zwlr_foreign_toplevel_manager_v1 ", file!(), line!(), &name);
+                    state.toplevels.bind_wlr(registry, name, version, qh);
+                }
+                "ext_foreign_toplevel_list_v1" => {
+                    eprintln!("{}:{} got event name={} ext_foreign_toplevel_list_v1 ", file!(), line!(), &name);
+                    state.toplevels.bind_ext(registry, name, version, qh);
+                }
                 "wl_output" => {
                     eprintln!("{}:{} got event name={} wl_output ", file!(), line!(), &name);
 
@@ -173,6 +405,26 @@ delegate_noop!(State: ignore wl_surface::WlSurface);
 delegate_noop!(State: ignore wl_shm::WlShm);
 delegate_noop!(State: ignore wl_shm_pool::WlShmPool);
 delegate_noop!(State: ignore wl_buffer::WlBuffer);
+delegate_noop!(State: ignore wl_region::WlRegion);
+delegate_noop!(State: ignore xdg_positioner::XdgPositioner);
+
+impl Dispatch<wl_callback::WlCallback, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // Only surface.frame() callbacks are requested; their time is the animation clock
+        if let wl_callback::Event::Done { callback_data } = event {
+            state.frame_pending = false;
+            state.anims.step(callback_data);
+            state.redraw_necessary = true;
+        }
+    }
+}
 
 impl State {
     fn init_xdg_surface(&mut self, qh: &QueueHandle<State>) {
@@ -211,7 +463,42 @@ impl State {
                 let uw = self.configured_w as u32;
                 let uh = self.configured_h as u32;
 
-                if let Err(e) = static_draw(&self.last_screenshot_px, &mut file, (uw, uh)) {
+                let magnify_amount = self.anims.value(&anim::AnimKey::MagnifyAmount).unwrap_or(0.0);
+                let magnify = match self.anims.value(&anim::AnimKey::MagnifyCenter) {
+                    Some(center) if self.config.magnify.enabled && magnify_amount > 0.0 => Some(layout::Magnify {
+                        pointer: center,
+                        max_scale: 1.0 + ((self.config.magnify.max_scale - 1.0) * magnify_amount),
+                        range: self.config.magnify.range,
+                    }),
+                    _ => None,
+                };
+                let params = layout::LayoutParams {
+                    edge: self.edge,
+                    style: self.config.style,
+                    shadow_w_px: self.theme.shadow.width_px,
+                    dock_angle_deg: self.theme.shelf.angle_deg,
+                    surface_scale: self.surface_scale,
+                    magnify,
+                    drag: self.drag_params(),
+                };
+                self.layout = layout::compute(uw, uh, &self.dock.items, &params);
+                if let Some(surface) = self.base_surface.as_ref() {
+                    self.toplevels.update_minimize_rects(surface, &self.dock.items, &self.layout);
+                }
+                // After the minimize rects, so windows don't minimize into a mid-air icon
+                for (i, item) in self.dock.items.iter().enumerate() {
+                    let launch = self.anims.value(&anim::AnimKey::LaunchBounce(item.id().to_string())).unwrap_or(0.0);
+                    let attention = self.anims.value(&anim::AnimKey::AttentionBounce(item.id().to_string())).unwrap_or(0.0);
+                    let lift = launch.max(attention) * layout::BOUNCE_HEIGHT * self.layout.icon_size as f32;
+                    self.layout.lift_icon(i, lift.round() as i32);
+                }
+                self.layout.drop_highlight = self.dnd.drag.as_ref().and_then(|d| d.target.as_deref())
+                    .and_then(|id| self.dock.items.iter().position(|item| item.id() == id));
+                self.layout.hovered = self.pointer_pos.filter(|_| self.layout.dragged.is_none()).and_then(|(x, y)| self.layout.item_at(x, y));
+                self.update_tooltip(qh);
+                self.update_poof(qh);
+
+                if let Err(e) = static_draw(&self.last_screenshot_px, &mut file, &self.dock.items, &self.layout, &mut self.icons, &self.theme) {
                     eprintln!("{:?}", e);
                 }
 
@@ -233,6 +520,10 @@ impl State {
                         Some(surface) => {
                             surface.attach(Some(&buffer), 0, 0);
                             surface.damage(0, 0, self.configured_w, self.configured_h);
+                            if self.anims.is_running() && !self.frame_pending {
+                                surface.frame(qh, ());
+                                self.frame_pending = true;
+                            }
                             surface.commit();
                         }
                         None => {
@@ -251,6 +542,566 @@ impl State {
 
     }
 
+    // The magnification center trails the pointer on a spring so fast swipes re-flow smoothly
+    pub fn magnify_follow(&mut self, surface_x: f64, surface_y: f64) {
+        if !self.config.magnify.enabled {
+            return;
+        }
+        let along = self.edge.along_dock((surface_x, surface_y));
+        if self.anims.value(&anim::AnimKey::MagnifyAmount).unwrap_or(0.0) <= 0.0 {
+            self.anims.set(anim::AnimKey::MagnifyCenter, along); // Nothing magnified yet, so no need to glide there
+        }
+        self.anims.spring_to(anim::AnimKey::MagnifyCenter, along, anim::Spring::critical(along, along, MAGNIFY_STIFFNESS, 0.5));
+        self.redraw_necessary = true;
+    }
+
+    // Open, move or close the hovered item's name popup to match the current layout
+    pub fn update_tooltip(&mut self, qh: &QueueHandle<State>) {
+        // An open menu or stack already says which item it is for
+        let hovered = if self.menu.is_some() || self.stack.is_some() { None } else { self.layout.hovered };
+        let wanted = hovered.and_then(|i| Some((self.dock.items.get(i)?.name.clone(), *self.layout.icons.get(i)?)));
+        let placement = |rect| popup::Placement::beside(rect, self.edge, self.theme.label.gap_px as i32);
+        match (self.tooltip.as_ref(), wanted) {
+            (Some((shown, id)), Some((name, rect))) if *shown == name => {
+                let id = *id;
+                self.popups.move_to(id, placement(rect), qh);
+            }
+            (current, wanted) => {
+                if let Some((_, id)) = current {
+                    self.popups.close(*id);
+                }
+                self.tooltip = None;
+                if let (Some((name, rect)), Some((parent, _))) = (wanted, self.xdg_surface.as_ref()) {
+                    let content = dock::label_image(&self.text, &name, &self.theme);
+                    if let Some(id) = self.popups.open(popup::PopupKind::Tooltip, content, placement(rect), parent, None, qh) {
+                        self.tooltip = Some((name, id));
+                    }
+                }
+            }
+        }
+    }
+
+    // The dragged item and how open each gap for it is; finished, closed gaps are forgotten
+    fn drag_params(&mut self) -> Option<layout::DragParams> {
+        if let Some(drag) = self.drag.as_mut().filter(|d| d.active) {
+            let item = self.dock.items.iter().position(|item| item.id() == drag.item_id)?;
+            let gaps = open_drop_gaps(&mut self.anims, &mut drag.gaps, drag.target);
+            return Some(layout::DragParams { item: Some(item), gaps });
+        }
+        let dnd = self.dnd.drag.as_mut().filter(|d| !d.gaps.is_empty())?;
+        let gaps = open_drop_gaps(&mut self.anims, &mut dnd.gaps, dnd.slot);
+        Some(layout::DragParams { item: None, gaps })
+    }
+
+    // Pointer moved with the left button down on an item: start dragging past the threshold,
+    // then keep the gap under the pointer open and the icon popup under the pointer
+    pub fn drag_motion(&mut self, pointer: (f64, f64), qh: &QueueHandle<State>) {
+        let drag = match self.drag.as_mut() {
+            Some(drag) => drag,
+            None => return,
+        };
+        drag.pointer = pointer;
+        let i = match self.dock.items.iter().position(|item| item.id() == drag.item_id) {
+            Some(i) => i,
+            None => {
+                // Its last window closed mid-drag
+                if let Some(id) = self.drag.take().and_then(|d| d.popup) {
+                    self.popups.close(id);
+                }
+                return;
+            }
+        };
+        if !drag.active {
+            if !drag.past_threshold() {
+                return;
+            }
+            // Lift it out, leaving its slot open so nothing moves yet
+            drag.active = true;
+            let home = layout::DropSlot { index: i, pinned: self.dock.items[i].pinned };
+            drag.target = Some(home);
+            drag.gaps = vec![home];
+            self.anims.set(anim::AnimKey::DropGap(home), 1.0);
+            if let Some((_, id)) = self.tooltip.take() {
+                self.popups.close(id);
+            }
+        }
+
+        let target = drag::drop_target(&self.layout, &self.dock.items, Some(i), pointer);
+        if target != drag.target {
+            move_drop_gap(&mut self.anims, &mut drag.gaps, drag.target, target);
+            drag.target = target;
+        }
+
+        let item = &self.dock.items[i];
+        let removing = target.is_none() && item.pinned;
+        let placement = popup::Placement::centered_on((pointer.0.round() as i32, pointer.1.round() as i32), (self.configured_w, self.configured_h));
+        match drag.popup {
+            Some(id) => {
+                self.popups.move_to(id, placement, qh);
+                if removing != drag.removing {
+                    let size = self.layout.icon_size.max(1) as u32;
+                    let content = drag::icon_image(item.icon.as_ref().and_then(|name| self.icons.get(name, size)), size, removing);
+                    self.popups.set_content(id, content, qh);
+                }
+            }
+            None => {
+                let size = self.layout.icon_size.max(1) as u32;
+                let content = drag::icon_image(item.icon.as_ref().and_then(|name| self.icons.get(name, size)), size, removing);
+                if let Some((parent, _)) = self.xdg_surface.as_ref() {
+                    drag.popup = self.popups.open(popup::PopupKind::Tooltip, content, placement, parent, None, qh);
+                }
+            }
+        }
+        drag.removing = removing;
+        self.redraw_necessary = true;
+    }
+
+    // Left button released after dragging: reorder, pin, or remove with a poof
+    pub fn finish_drag(&mut self, drag: drag::Drag, qh: &QueueHandle<State>) {
+        if let Some(id) = drag.popup {
+            self.popups.close(id);
+        }
+        for slot in drag.gaps.iter() {
+            self.anims.remove(&anim::AnimKey::DropGap(*slot));
+        }
+        self.redraw_necessary = true;
+        let i = match self.dock.items.iter().position(|item| item.id() == drag.item_id) {
+            Some(i) => i,
+            None => return,
+        };
+        match drag.target {
+            None if drag.removing => {
+                self.unpin_item(i);
+                self.poof_at(drag.pointer, qh);
+            }
+            None => { } // Running-only items can't be removed; it just goes back
+            Some(slot) => self.move_item(i, slot),
+        }
+    }
+
+    pub fn poof_at(&mut self, (x, y): (f64, f64), qh: &QueueHandle<State>) {
+        if let Some((id, _)) = self.poof.take() {
+            self.popups.close(id);
+        }
+        let parent = match self.xdg_surface.as_ref() {
+            Some((parent, _)) => parent,
+            None => return,
+        };
+        let size = ((self.layout.icon_size.max(16) * 3) / 2) as u32;
+        let placement = popup::Placement::centered_on((x.round() as i32, y.round() as i32), (self.configured_w, self.configured_h));
+        if let Some(id) = self.popups.open(popup::PopupKind::Tooltip, drag::poof_image(0.0, size), placement, parent, None, qh) {
+            self.poof = Some((id, size));
+            self.anims.remove(&anim::AnimKey::Poof);
+            self.anims.tween(anim::AnimKey::Poof, 0.0, 1.0, POOF_MS, anim::Easing::Linear);
+        }
+    }
+
+    // Advance the smoke popup with the animation clock, and close it once it has cleared
+    fn update_poof(&mut self, qh: &QueueHandle<State>) {
+        let (id, size) = match self.poof {
+            Some(poof) => poof,
+            None => return,
+        };
+        match self.anims.value(&anim::AnimKey::Poof) {
+            Some(t) if t < 1.0 => self.popups.set_content(id, drag::poof_image(t, size), qh),
+            _ => {
+                self.popups.close(id);
+                self.poof = None;
+                self.anims.remove(&anim::AnimKey::Poof);
+            }
+        }
+    }
+
+    // Files dragged in from another client magnify the dock like the pointer does
+    pub fn on_dnd_enter(&mut self, pointer: (f64, f64)) {
+        self.magnify_follow(pointer.0, pointer.1);
+        self.anims.tween(anim::AnimKey::MagnifyAmount, 0.0, 1.0, MAGNIFY_IN_MS, anim::Easing::EaseOutCubic);
+        self.on_dnd_motion(pointer);
+    }
+
+    // Over an icon that opens every dragged file, highlight it; anywhere else on the shelf,
+    // open a gap for adding the files as items, if they are things that can be added
+    pub fn on_dnd_motion(&mut self, pointer: (f64, f64)) {
+        self.magnify_follow(pointer.0, pointer.1);
+        let drag = match self.dnd.drag.as_mut() {
+            Some(drag) => drag,
+            None => return,
+        };
+        drag.pointer = pointer;
+        if drag.is_receiving() {
+            return; // Nothing to decide until the files are known
+        }
+        let target = self.layout.item_at(pointer.0, pointer.1)
+            .and_then(|i| self.dock.items.get(i))
+            .filter(|item| item.accepts(&drag.mime_types, &self.mime))
+            .map(|item| item.id().to_string());
+        let slot = match target {
+            None if !drag.adds.is_empty() => drag::drop_target(&self.layout, &self.dock.items, None, pointer),
+            _ => None,
+        };
+        drag.target = target;
+        if slot != drag.slot {
+            move_drop_gap(&mut self.anims, &mut drag.gaps, drag.slot, slot);
+            drag.slot = slot;
+        }
+        drag.accept();
+        self.redraw_necessary = true;
+    }
+
+    // More of the dragged URI list arrived; once it is all here, work out what dropping them
+    // would do and tell the source
+    pub fn on_dnd_readable(&mut self) {
+        let uris = match self.dnd.read_uris() {
+            Some(Ok(uris)) => uris,
+            Some(Err(e)) => {
+                eprintln!("{}:{} {}", file!(), line!(), e);
+                return; // Left unaccepted, so dropping it here does nothing
+            }
+            None => return,
+        };
+        let drag = match self.dnd.drag.as_mut() {
+            Some(drag) => drag,
+            None => return,
+        };
+        drag.mime_types = uris.iter().map(|uri| self.mime.for_uri(uri)).collect();
+        drag.adds = uris.iter().map(|uri| dock::DockItem::from_uri(uri, &self.desktop_index)).collect::<Option<Vec<_>>>().unwrap_or_default();
+        drag.uris = uris;
+        let pointer = drag.pointer;
+        self.on_dnd_motion(pointer);
+    }
+
+    pub fn on_dnd_leave(&mut self, drag: &dnd::DndDrag) {
+        for slot in drag.gaps.iter() {
+            self.anims.remove(&anim::AnimKey::DropGap(*slot));
+        }
+        if self.pointer_pos.is_none() {
+            self.anims.tween(anim::AnimKey::MagnifyAmount, 0.0, 0.0, MAGNIFY_OUT_MS, anim::Easing::EaseInOutCubic);
+        }
+        self.redraw_necessary = true;
+    }
+
+    pub fn on_dnd_drop(&mut self, drag: &dnd::DndDrag) {
+        // Windows may have closed and shuffled the dock since the last motion; a target that
+        // has gone away takes nothing
+        let target = drag.target.as_deref().and_then(|id| self.dock.items.iter().position(|item| item.id() == id));
+        match (target, drag.slot) {
+            (Some(i), _) if self.dock.items[i].kind == dock::ItemKind::Trash => self.trash_uris(&drag.uris),
+            (Some(i), _) => self.launch_item_with(i, &drag.uris),
+            (None, Some(slot)) => self.add_items(slot.index, drag.adds.clone()),
+            (None, None) => { }
+        }
+    }
+
+    // Move dropped files into the trash; the watch on it updates the icon
+    pub fn trash_uris(&mut self, uris: &[String]) {
+        for path in uris.iter().filter_map(|uri| desktop::uri_to_path(uri)) {
+            match trash::trash(&path) {
+                Ok(name) => eprintln!("{}:{} trashed {:?} as {:?}", file!(), line!(), path, name),
+                Err(e) => eprintln!("{}:{} {}", file!(), line!(), e),
+            }
+        }
+    }
+
+    // Pin things dropped on the shelf, in the order they were dragged, starting at `at`
+    pub fn add_items(&mut self, at: usize, items: Vec<dock::DockItem>) {
+        let before = self.dock.pinned();
+        let mut at = at;
+        for item in items {
+            eprintln!("{}:{} adding {} to the dock", file!(), line!(), item.id());
+            at = self.dock.add(item, at) + 1;
+        }
+        // A new app item takes over windows some running-only item had
+        self.on_toplevels_changed();
+        if self.dock.pinned() != before {
+            self.save_config();
+        }
+        self.redraw_necessary = true;
+    }
+
+    // The compositor closed a popup on its own (eg an outside click during a grab)
+    pub fn on_popup_dismissed(&mut self, id: popup::PopupId) {
+        if self.poof.map(|(poof_id, _)| poof_id) == Some(id) {
+            self.poof = None;
+        }
+        if let Some(drag) = self.drag.as_mut() && drag.popup == Some(id) {
+            drag.popup = None;
+        }
+        if self.tooltip.as_ref().map(|(_, tooltip_id)| *tooltip_id) == Some(id) {
+            self.tooltip = None;
+        }
+        if self.menu.as_ref().map(|m| m.popup) == Some(id) {
+            self.menu = None;
+            self.redraw_necessary = true; // The tooltip may come back
+        }
+        if self.stack.as_ref().and_then(|s| s.popup) == Some(id) {
+            self.stack = None;
+            if let Some(watcher) = self.watcher.as_mut() {
+                watcher.unwatch_kind(watch::WatchKind::Stack);
+            }
+            self.redraw_necessary = true;
+        }
+    }
+
+    // Folder item i's stack, grabbing input like a menu. It opens on the click's release,
+    // which some compositors won't start a grab from; it then closes on the next click anywhere.
+    pub fn open_stack(&mut self, i: usize, qh: &QueueHandle<State>) {
+        self.close_menu();
+        self.close_stack();
+        if let Some((_, id)) = self.tooltip.take() {
+            self.popups.close(id);
+        }
+        let (item, rect) = match (self.dock.items.get(i), self.layout.icons.get(i)) {
+            (Some(item), Some(rect)) => (item, *rect),
+            _ => return,
+        };
+        let dir = match &item.kind {
+            dock::ItemKind::Folder(dir) => dir,
+            _ => return,
+        };
+        let parent = match self.xdg_surface.as_ref() {
+            Some((parent, _)) => parent,
+            None => return,
+        };
+        let mut stack = stack::Stack::new(item.id(), dir, item.stack, self.edge, &self.mime, &self.text, &self.theme);
+        let content = stack.render(&mut self.icons, &self.theme);
+        let placement = popup::Placement::beside(rect, self.edge, self.theme.label.gap_px as i32);
+        let grab = self.seat.as_ref().map(|seat| (seat, self.last_input_serial));
+        stack.popup = self.popups.open(popup::PopupKind::Menu, content, placement, parent, grab, qh);
+        if stack.popup.is_none() {
+            return;
+        }
+        // Files coming and going show up while it is open
+        if let Some(watcher) = self.watcher.as_mut() && let Err(e) = watcher.watch_dir(dir, watch::WatchKind::Stack) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+        self.stack = Some(stack);
+    }
+
+    pub fn close_stack(&mut self) {
+        if let Some(stack) = self.stack.take() {
+            if let Some(id) = stack.popup {
+                self.popups.close(id);
+            }
+            if let Some(watcher) = self.watcher.as_mut() {
+                watcher.unwatch_kind(watch::WatchKind::Stack);
+            }
+            self.redraw_necessary = true;
+        }
+    }
+
+    // Redraw the open stack after its hover, scroll or files changed
+    fn refresh_stack(&mut self, qh: &QueueHandle<State>) {
+        if let Some(stack) = self.stack.as_ref() && let Some(id) = stack.popup {
+            let content = stack.render(&mut self.icons, &self.theme);
+            self.popups.set_content(id, content, qh);
+        }
+    }
+
+    pub fn on_stack_pointer(&mut self, pos: Option<(f64, f64)>, qh: &QueueHandle<State>) {
+        let theme = &self.theme;
+        if let Some(stack) = self.stack.as_mut() {
+            let hovered = pos.and_then(|pos| stack.hit(pos, theme));
+            if hovered != stack.hovered {
+                stack.hovered = hovered;
+                self.refresh_stack(qh);
+            }
+        }
+    }
+
+    // Wheel scrolling in a grid, a row per notch
+    pub fn on_stack_scroll(&mut self, value: f64, qh: &QueueHandle<State>) {
+        self.scroll_accum += value;
+        if self.scroll_accum.abs() < SCROLL_STEP {
+            return;
+        }
+        let rows = if self.scroll_accum > 0.0 { 1 } else { -1 };
+        self.scroll_accum = 0.0;
+        let pos = self.pointer_popup.map(|(_, pos)| pos);
+        let theme = &self.theme;
+        if let Some(stack) = self.stack.as_mut() && stack.scroll_by(rows) {
+            // Whatever is under the pointer now
+            stack.hovered = pos.and_then(|pos| stack.hit(pos, theme));
+            self.refresh_stack(qh);
+        }
+    }
+
+    // Escape closes the stack, Enter opens the hovered file
+    pub fn on_stack_key(&mut self, key: u32) {
+        match key {
+            KEY_ESC => self.close_stack(),
+            KEY_ENTER | KEY_KPENTER => self.pick_stack_entry(),
+            _ => { }
+        }
+    }
+
+    // Open the hovered file, or the folder itself, with its default app and close the stack
+    pub fn pick_stack_entry(&mut self) {
+        let path = match self.stack.as_ref().and_then(|stack| Some((stack, stack.hovered?))) {
+            Some((stack, stack::StackHit::Entry(i))) => stack.entries[i].path.clone(),
+            Some((stack, stack::StackHit::Open)) => stack.dir.clone(),
+            None => return,
+        };
+        self.close_stack();
+        eprintln!("{}:{} opening {:?}", file!(), line!(), path);
+        if let Err(e) = desktop::spawn_argv(&["xdg-open".to_string(), path.to_string_lossy().to_string()]) {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+    }
+
+    // The open stack's folder changed on disk
+    fn on_stack_dir_changed(&mut self, qh: &QueueHandle<State>) {
+        if let Some(stack) = self.stack.as_mut() {
+            stack.refresh(&self.mime, &self.text, &self.theme);
+            self.refresh_stack(qh);
+        }
+    }
+
+    // Right-click menu for item i, grabbing input with the serial of the click that opened it
+    pub fn open_menu(&mut self, i: usize, qh: &QueueHandle<State>) {
+        self.close_menu();
+        if let Some((_, id)) = self.tooltip.take() {
+            self.popups.close(id);
+        }
+        let (item, rect) = match (self.dock.items.get(i), self.layout.icons.get(i)) {
+            (Some(item), Some(rect)) => (item, *rect),
+            _ => return,
+        };
+        let parent = match self.xdg_surface.as_ref() {
+            Some((parent, _)) => parent,
+            None => return,
+        };
+        let entry = item.desktop_id.as_deref().and_then(|id| self.desktop_index.by_id(id));
+        let recent = self.recent.for_item(item, RECENT_FILES_IN_MENU);
+        let rows = menu::item_rows(item, &self.toplevels, entry, &recent);
+        let content = menu::render(&rows, None, &self.text, &self.theme);
+        let placement = popup::Placement::beside(rect, self.edge, self.theme.label.gap_px as i32);
+        let grab = self.seat.as_ref().map(|seat| (seat, self.last_input_serial));
+        if let Some(popup) = self.popups.open(popup::PopupKind::Menu, content, placement, parent, grab, qh) {
+            self.menu = Some(menu::Menu { item_id: item.id().to_string(), rows, selected: None, popup });
+        }
+    }
+
+    pub fn close_menu(&mut self) {
+        if let Some(menu) = self.menu.take() {
+            self.popups.close(menu.popup);
+            self.redraw_necessary = true;
+        }
+    }
+
+    // Redraw the open menu after its selection changed
+    fn refresh_menu(&mut self, qh: &QueueHandle<State>) {
+        if let Some(menu) = self.menu.as_ref() {
+            let content = menu::render(&menu.rows, menu.selected, &self.text, &self.theme);
+            self.popups.set_content(menu.popup, content, qh);
+        }
+    }
+
+    pub fn on_menu_pointer(&mut self, y: Option<f64>, qh: &QueueHandle<State>) {
+        let theme = &self.theme;
+        if let Some(menu) = self.menu.as_mut() {
+            let selected = y.and_then(|y| menu.row_at(y, theme));
+            if selected != menu.selected {
+                menu.selected = selected;
+                self.refresh_menu(qh);
+            }
+        }
+    }
+
+    // Up/Down/Home/End move the selection, Enter/Space pick it, Escape closes the menu
+    pub fn on_menu_key(&mut self, key: u32, qh: &QueueHandle<State>) {
+        let menu = match self.menu.as_mut() {
+            Some(menu) => menu,
+            None => return,
+        };
+        match key {
+            KEY_UP => menu.step(-1),
+            KEY_DOWN => menu.step(1),
+            KEY_HOME => menu.select_first(),
+            KEY_END => menu.select_last(),
+            KEY_ENTER | KEY_KPENTER | KEY_SPACE => {
+                self.pick_menu_row();
+                return;
+            }
+            KEY_ESC => {
+                self.close_menu();
+                return;
+            }
+            _ => return,
+        }
+        self.refresh_menu(qh);
+    }
+
+    // Close the menu and do what its selected row says; with nothing selected the menu stays open
+    pub fn pick_menu_row(&mut self) {
+        let (item_id, action) = match self.menu.as_ref().and_then(|menu| Some((menu.item_id.clone(), menu.selected_action()?))) {
+            Some(picked) => picked,
+            None => return,
+        };
+        self.close_menu();
+        self.run_menu_action(&item_id, action);
+    }
+
+    pub fn run_menu_action(&mut self, item_id: &str, action: menu::MenuAction) {
+        let i = match self.dock.items.iter().position(|item| item.id() == item_id) {
+            Some(i) => i,
+            None => return, // Its last window closed while the menu was open
+        };
+        let item = &self.dock.items[i];
+        let entry = item.desktop_id.as_deref().and_then(|id| self.desktop_index.by_id(id));
+        match action {
+            menu::MenuAction::FocusWindow(handle) => {
+                if let Some(seat) = self.seat.as_ref() && self.toplevels.list.iter().any(|t| t.handle == handle) {
+                    self.toplevels.activate(&handle, seat);
+                }
+            }
+            menu::MenuAction::OpenRecent(uri) => {
+                match item.launch_with(&[uri]) {
+                    // A running app usually opens it in a window it already has
+                    Ok(()) if item.running == 0 => {
+                        self.anims.bounce(anim::AnimKey::LaunchBounce(item_id.to_string()), LAUNCH_BOUNCE_HOPS, BOUNCE_HOP_MS);
+                        self.redraw_necessary = true;
+                    }
+                    Ok(()) => { }
+                    Err(e) => eprintln!("{}:{} {}", file!(), line!(), e),
+                }
+            }
+            menu::MenuAction::DesktopAction(id) => {
+                if let Err(e) = self.launch_item_action(i, &id) {
+                    eprintln!("{}:{} {}", file!(), line!(), e);
+                }
+            }
+            menu::MenuAction::Pin => self.pin_item(i),
+            menu::MenuAction::Unpin => self.unpin_item(i),
+            menu::MenuAction::ToggleAutostart => {
+                if let Some(entry) = entry && let Err(e) = desktop::set_autostart(entry, !desktop::is_autostart(&entry.id)) {
+                    eprintln!("{}:{} {}", file!(), line!(), e);
+                }
+            }
+            menu::MenuAction::ShowInFileManager => {
+                match (&item.kind, entry) {
+                    (dock::ItemKind::Folder(path), _) => desktop::show_in_file_manager(path),
+                    (_, Some(entry)) => desktop::show_in_file_manager(&entry.path),
+                    _ => { }
+                }
+            }
+            menu::MenuAction::SetStackView(view) => {
+                self.dock.items[i].stack.view = view;
+                self.save_config();
+            }
+            menu::MenuAction::SetStackSort(sort) => {
+                self.dock.items[i].stack.sort = sort;
+                self.save_config();
+            }
+            menu::MenuAction::Open => self.launch_item(i),
+            menu::MenuAction::EmptyTrash => trash::empty(),
+            menu::MenuAction::Quit => {
+                for window in item.windows(&self.toplevels) {
+                    self.toplevels.close(&window.handle);
+                }
+            }
+        }
+    }
+
     pub fn draw_from_stolen(&mut self) {
         if let Some(registry) = self.stolen_registry.clone() { // ugh .clones
             if let Some(qh) = self.stolen_qh.clone() {
@@ -259,29 +1110,324 @@ impl State {
         }
     }
 
+    // Called after any toplevel finishes a batch of changes (or goes away)
+    pub fn on_toplevels_changed(&mut self) {
+        let running_before: Vec<(String, usize)> = self.dock.items.iter().map(|item| (item.id().to_string(), item.running)).collect();
+        if self.dock.sync_running(self.toplevels.visible(), &self.desktop_index) {
+            self.redraw_necessary = true;
+        }
+        for item in self.dock.items.iter() {
+            let id = item.id().to_string();
+            // A launch is over once a new window shows up
+            let before = running_before.iter().find(|(before_id, _)| *before_id == id).map(|(_, n)| *n).unwrap_or(0);
+            if item.running > before {
+                self.anims.stop_bounce(&anim::AnimKey::LaunchBounce(id.clone()));
+            }
+            // Attention is over once the user looks at the app
+            if item.windows(&self.toplevels).iter().any(|t| t.activated) {
+                self.anims.stop_bounce(&anim::AnimKey::AttentionBounce(id));
+            }
+        }
+    }
+
+    pub fn on_ipc_requests(&mut self) {
+        let requests = match self.ipc.as_ref() {
+            Some(server) => server.accept(),
+            None => return,
+        };
+        for request in requests {
+            let result = match request.command.clone() {
+                Ok(ipc::Command::Attention(app_id)) => self.request_attention(&app_id),
+                Ok(ipc::Command::Action { app_id, action }) => {
+                    match self.dock.items.iter().position(|item| item.id() == app_id || item.matches_app_id(&app_id)) {
+                        Some(i) => self.launch_item_action(i, &action),
+                        None => Err(format!("nothing on the dock matches app_id {:?}", app_id)),
+                    }
+                }
+                Err(e) => Err(e),
+            };
+            request.reply(result);
+        }
+    }
+
+    // Bounce the app's icon, unless the user is already looking at it
+    pub fn request_attention(&mut self, app_id: &str) -> Result<(), String> {
+        let item = self.dock.items.iter().find(|item| item.matches_app_id(app_id))
+            .ok_or_else(|| format!("nothing on the dock matches app_id {:?}", app_id))?;
+        if item.windows(&self.toplevels).iter().any(|t| t.activated) {
+            return Ok(());
+        }
+        self.anims.bounce(anim::AnimKey::AttentionBounce(item.id().to_string()), ATTENTION_BOUNCE_HOPS, BOUNCE_HOP_MS);
+        self.redraw_necessary = true;
+        Ok(())
+    }
+
+    // Launch a new instance and bounce until its window appears
+    pub fn launch_item(&mut self, i: usize) {
+        let item = match self.dock.items.get(i) {
+            Some(item) => item,
+            None => return,
+        };
+        eprintln!("{}:{} launching {}", file!(), line!(), item.name);
+        match item.launch() {
+            // Folders and links never get a window of their own to stop the bounce
+            Ok(()) if item.kind != dock::ItemKind::App => { }
+            Ok(()) => {
+                self.anims.bounce(anim::AnimKey::LaunchBounce(item.id().to_string()), LAUNCH_BOUNCE_HOPS, BOUNCE_HOP_MS);
+                self.redraw_necessary = true;
+            }
+            Err(e) => eprintln!("{}:{} {}", file!(), line!(), e),
+        }
+    }
+
+    // Open documents with the item's app; bounces like a launch unless the app is already open,
+    // since most apps hand the files to their existing window
+    pub fn launch_item_with(&mut self, i: usize, uris: &[String]) {
+        let item = match self.dock.items.get(i) {
+            Some(item) => item,
+            None => return,
+        };
+        eprintln!("{}:{} opening {} file(s) with {}", file!(), line!(), uris.len(), item.name);
+        match item.launch_with(uris) {
+            Ok(()) if item.running == 0 => {
+                self.anims.bounce(anim::AnimKey::LaunchBounce(item.id().to_string()), LAUNCH_BOUNCE_HOPS, BOUNCE_HOP_MS);
+                self.redraw_necessary = true;
+            }
+            Ok(()) => { }
+            Err(e) => eprintln!("{}:{} {}", file!(), line!(), e),
+        }
+    }
+
+    // One of the item's desktop file actions; bounces like a launch since most open a window
+    pub fn launch_item_action(&mut self, i: usize, action_id: &str) -> Result<(), String> {
+        let item = self.dock.items.get(i).ok_or_else(|| format!("no dock item {}", i))?;
+        eprintln!("{}:{} launching {} action {:?}", file!(), line!(), item.name, action_id);
+        item.launch_action(action_id).map_err(|e| e.to_string())?;
+        self.anims.bounce(anim::AnimKey::LaunchBounce(item.id().to_string()), LAUNCH_BOUNCE_HOPS, BOUNCE_HOP_MS);
+        self.redraw_necessary = true;
+        Ok(())
+    }
+
+    pub fn on_watched_files_changed(&mut self, qh: &QueueHandle<State>) {
+        let changes = match self.watcher.as_ref() {
+            Some(watcher) => watcher.read_changes(),
+            None => return,
+        };
+        for change in changes {
+            match change {
+                watch::WatchKind::Config => self.reload_config(),
+                watch::WatchKind::Theme => self.reload_theme(),
+                // Only read when a menu opens, so there is nothing to redraw
+                watch::WatchKind::RecentFiles => self.recent = recent::RecentFiles::load(),
+                watch::WatchKind::Stack => self.on_stack_dir_changed(qh),
+                watch::WatchKind::Trash => {
+                    if self.dock.set_trash_full(!trash::is_empty()) {
+                        self.redraw_necessary = true;
+                    }
+                }
+            }
+        }
+    }
+
+    // Parse errors leave the running dock untouched
+    pub fn reload_config(&mut self) {
+        match config::Config::load() {
+            Ok(config) => {
+                if config == self.config {
+                    return; // Most likely our own save_config()
+                }
+                eprintln!("{}:{} reloading {:?}", file!(), line!(), config::config_path());
+                self.dock = dock::Dock::from_config(&config.pinned, &self.desktop_index);
+                if config.edge != self.edge {
+                    eprintln!("{}:{} edge = {:?} will apply after a restart", file!(), line!(), config.edge);
+                }
+                if config.surface_scale() > self.surface_scale {
+                    eprintln!("{}:{} magnified icons are clipped to the current surface until a restart", file!(), line!());
+                }
+                let theme_changed = config.theme != self.config.theme;
+                self.config = config;
+                if theme_changed {
+                    self.watch_theme();
+                    self.reload_theme();
+                }
+                self.dock.sync_running(self.toplevels.visible(), &self.desktop_index);
+                self.icons.clear(); // Overrides may point at icons that were just edited
+                self.redraw_necessary = true;
+            }
+            Err(e) => {
+                eprintln!("{}:{} {}, keeping the previous config", file!(), line!(), e);
+            }
+        }
+    }
+
+    // Built-in themes have no file, so there is nothing to watch for them
+    pub fn watch_theme(&mut self) {
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.unwatch_kind(watch::WatchKind::Theme);
+            for path in theme::theme_files(&self.config.theme) {
+                if let Err(e) = watcher.watch_file(&path, watch::WatchKind::Theme) {
+                    eprintln!("{}:{} {}", file!(), line!(), e);
+                }
+            }
+        }
+    }
+
+    pub fn reload_theme(&mut self) {
+        match theme::Theme::load(&self.config.theme) {
+            Ok(theme) => {
+                if theme != self.theme {
+                    eprintln!("{}:{} applying theme {:?}", file!(), line!(), theme.name);
+                    let skin_changed = theme.skin != self.theme.skin;
+                    self.theme = theme;
+                    if skin_changed {
+                        self.watch_theme(); // A different set of images to watch
+                    }
+                    if self.theme.label.font != self.text.family {
+                        self.text = text::TextRenderer::load(&self.theme.label.font);
+                    }
+                    self.redraw_necessary = true;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}:{} {}, keeping the previous theme", file!(), line!(), e);
+            }
+        }
+    }
+
+    // Pinned items live in config.toml; every pin/unpin/reorder goes through here
+    pub fn save_config(&mut self) {
+        self.config.pinned = self.dock.pinned();
+        if let Err(e) = self.config.save() {
+            eprintln!("{}:{} {}", file!(), line!(), e);
+        }
+    }
+
+    pub fn pin_item(&mut self, i: usize) {
+        self.dock.pin(i);
+        self.save_config();
+        self.redraw_necessary = true;
+    }
+
+    pub fn unpin_item(&mut self, i: usize) {
+        self.dock.unpin(i);
+        self.save_config();
+        self.redraw_necessary = true;
+    }
+
+    // Move item `from` to `slot`, pinning it first if the slot is in the pinned section.
+    // Reordering running-only items changes nothing in the config, so it isn't rewritten.
+    pub fn move_item(&mut self, from: usize, slot: layout::DropSlot) {
+        let before = self.dock.pinned();
+        let mut from = from;
+        if slot.pinned && self.dock.items.get(from).is_some_and(|item| !item.pinned) {
+            self.dock.pin(from);
+            from = self.dock.items.iter().take_while(|item| item.pinned).count() - 1;
+        }
+        self.dock.move_item(from, slot.index);
+        if self.dock.pinned() != before {
+            self.save_config();
+        }
+        self.redraw_necessary = true;
+    }
+
+    // The item under the pointer. The layout is from the last draw, and since then closed
+    // windows or a config reload may have taken items off the dock.
+    pub fn item_under_pointer(&self) -> Option<usize> {
+        let (x, y) = self.pointer_pos?;
+        self.layout.item_at(x, y).filter(|i| *i < self.dock.items.len())
+    }
+
+    pub fn click_item(&mut self, i: usize) {
+        let item = match self.dock.items.get(i) {
+            Some(item) => item,
+            None => return,
+        };
+        let mut windows = item.windows(&self.toplevels);
+        if windows.is_empty() {
+            self.launch_item(i);
+            return;
+        }
+        let seat = match self.seat.as_ref() {
+            Some(seat) => seat,
+            None => {
+                eprintln!("{}:{} no wl_seat to activate windows with", file!(), line!());
+                return;
+            }
+        };
+        windows.sort_by_key(|t| t.focus_serial);
+        let newest = windows[windows.len() - 1];
+        if !newest.activated || newest.minimized {
+            self.toplevels.activate(&newest.handle, seat);
+            return;
+        }
+        match self.config.click_policy {
+            dock::ClickPolicy::Minimize => self.toplevels.minimize(&newest.handle),
+            // Least recently focused first, so repeated clicks visit every window
+            dock::ClickPolicy::Cycle => self.toplevels.activate(&windows[0].handle, seat),
+            dock::ClickPolicy::Activate => self.toplevels.activate(&newest.handle, seat),
+        }
+    }
+
+    // direction > 0 moves to the next window of the item, < 0 to the previous one
+    pub fn cycle_item_windows(&mut self, i: usize, direction: i32) {
+        let item = match self.dock.items.get(i) {
+            Some(item) => item,
+            None => return,
+        };
+        let windows = item.windows(&self.toplevels);
+        if windows.is_empty() {
+            return;
+        }
+        let seat = match self.seat.as_ref() {
+            Some(seat) => seat,
+            None => return,
+        };
+        let n = windows.len() as i32;
+        let next = match windows.iter().position(|t| t.activated) {
+            Some(current) => (current as i32 + direction).rem_euclid(n),
+            None => windows.iter().enumerate().max_by_key(|(_, t)| t.focus_serial).map(|(i, _)| i as i32).unwrap_or(0),
+        };
+        self.toplevels.activate(&windows[next as usize].handle, seat);
+    }
+
     pub fn take_screenshot(&mut self) {
         eprintln!("Begin take_screenshot");
         if self.configured_w < 4 || self.configured_h < 4 {
             return; // invalid to take screenshot 0x0 in size
         }
-        let dock_w = self.configured_w / 2;
-        let dock_lr_margin = (self.configured_w - dock_w) / 2;
+        // Everything is worked out as if for a bottom dock, then turned to face the real edge
+        let edge = self.edge;
+        let (space_w, space_h) = edge.dock_space_size(self.configured_w as u32, self.configured_h as u32);
+        // The reflection is sized to the shelf band, not the magnification headroom
+        let (space_w, space_h) = (space_w as i32, layout::band_height(space_h, self.surface_scale) as i32);
+        let dock_w = space_w / 2;
+        let dock_lr_margin = (space_w - dock_w) / 2;
         let begin_x = dock_lr_margin;
-        let end_x = self.configured_w - dock_lr_margin;
 
-        let screenshot_y_above_dock_dist = self.configured_h; // We capture 2x the dock's height; no need for entire screen!
+        let screenshot_y_above_dock_dist = space_h; // We capture 2x the dock's height; no need for entire screen!
+        let capture_depth = space_h + screenshot_y_above_dock_dist;
 
         //eprintln!("size = {:?}", (dock_w as i32, (self.configured_h + screenshot_y_above_dock_dist) as i32));
 
         let mut screenshot_px = Vec::<[u8; 4]>::with_capacity((self.configured_w * self.configured_h) as usize); // Screenshot turns into array of [b as u8, g as u8, r as u8, a as u8] values
+        let mut capture_w_h = (0, 0);
         if let Ok(ref mut haruhi_shot) = self.haruhi_shot {
+            let (display_w, display_h) = haruhi_shot.display_logic_size[0];
+            // The reflection always comes from the side of the dock facing away from the screen edge
+            let (capture_x, capture_y, capture_w, capture_h) = match edge {
+                layout::Edge::Bottom => (begin_x, display_h - capture_depth, dock_w, capture_depth),
+                layout::Edge::Top => (begin_x, 0, dock_w, capture_depth),
+                layout::Edge::Left => (0, begin_x, capture_depth, dock_w),
+                layout::Edge::Right => (display_w - capture_depth, begin_x, capture_depth, dock_w),
+            };
+            capture_w_h = (capture_w as u32, capture_h as u32);
             match haruhi_shot.capture_output_frame(
                 &haruhi_shot.displays[0].clone(),
-                (dock_w as i32, (self.configured_h + screenshot_y_above_dock_dist) as i32), // output w,h
+                (capture_w, capture_h), // output w,h
                 haruhi_shot.display_transform[0],
                 Some((
-                    begin_x as i32, haruhi_shot.display_logic_size[0].1 - (self.configured_h + screenshot_y_above_dock_dist) as i32, // x,y
-                    dock_w as i32, (self.configured_h + screenshot_y_above_dock_dist) as i32 // w,h
+                    capture_x, capture_y, // x,y
+                    capture_w, capture_h // w,h
                 ))
             ) {
                 Ok(Some(frame_buff_info)) => {
@@ -317,6 +1463,9 @@ impl State {
             }
         }
         if screenshot_px.len() > 0 {
+            if screenshot_px.len() == (capture_w_h.0 * capture_w_h.1) as usize {
+                screenshot_px = edge.px_to_dock_space(&screenshot_px, capture_w_h.0, capture_w_h.1);
+            }
             self.last_screenshot_px.clear();
             self.last_screenshot_px.append(&mut screenshot_px);
             // screenshot_px is now empty
@@ -325,40 +1474,141 @@ impl State {
 
 }
 
-fn shadow_falloff_f(dist_to_edge: f32) -> u8 {
-    return ((dist_to_edge as f32 / SHADOW_W_PX as f32) * 255.0) as u8;
+// How open each drop gap is, forgetting those that have finished closing
+fn open_drop_gaps(anims: &mut anim::Animations, gaps: &mut Vec<layout::DropSlot>, target: Option<layout::DropSlot>) -> Vec<(layout::DropSlot, f32)> {
+    gaps.retain(|slot| {
+        let key = anim::AnimKey::DropGap(*slot);
+        let closed = anims.value(&key).unwrap_or(0.0) <= 0.0 && target != Some(*slot);
+        if closed {
+            anims.remove(&key);
+        }
+        !closed
+    });
+    gaps.iter().map(|slot| (*slot, anims.value(&anim::AnimKey::DropGap(*slot)).unwrap_or(0.0))).collect()
 }
 
-fn shadow_falloff_i(dist_to_edge: i32) -> u8 {
-    return ((dist_to_edge as f32 / SHADOW_W_PX as f32) * 255.0).round() as u8;
-    //return (( (dist_to_edge as f32 / 3.46).powf(2.0) / SHADOW_W_PX as f32) * 255.0) as u8;
-    //return (( (dist_to_edge as f32 / 5.23).powf(3.0) / SHADOW_W_PX as f32) * 255.0) as u8;
-    //return (( ( (dist_to_edge as f32 - 1.0) * 8.0).powf(0.6) / SHADOW_W_PX as f32) * 255.0) as u8;
-    //return ((dist_to_edge as f32 / SHADOW_W_PX as f32) * 220.0).round() as u8;
+// Close the gap at the old drop target and open one at the new
+fn move_drop_gap(anims: &mut anim::Animations, gaps: &mut Vec<layout::DropSlot>, old: Option<layout::DropSlot>, new: Option<layout::DropSlot>) {
+    if let Some(old) = old {
+        anims.tween(anim::AnimKey::DropGap(old), 0.0, 0.0, DROP_GAP_MS, anim::Easing::EaseInOutCubic);
+    }
+    if let Some(new) = new {
+        anims.tween(anim::AnimKey::DropGap(new), 0.0, 1.0, DROP_GAP_MS, anim::Easing::EaseInOutCubic);
+        if !gaps.contains(&new) {
+            gaps.push(new);
+        }
+    }
+}
+
+fn shadow_falloff_f(dist_to_edge: f32, shadow_w_px: i32) -> u8 {
+    ((dist_to_edge / shadow_w_px as f32) * 255.0) as u8
+}
+
+fn shadow_falloff_i(dist_to_edge: i32, shadow_w_px: i32) -> u8 {
+    ((dist_to_edge as f32 / shadow_w_px as f32) * 255.0).round() as u8
+    //return (( (dist_to_edge as f32 / 3.46).powf(2.0) / shadow_w_px as f32) * 255.0) as u8;
+    //return (( (dist_to_edge as f32 / 5.23).powf(3.0) / shadow_w_px as f32) * 255.0) as u8;
+    //return (( ( (dist_to_edge as f32 - 1.0) * 8.0).powf(0.6) / shadow_w_px as f32) * 255.0) as u8;
+    //return ((dist_to_edge as f32 / shadow_w_px as f32) * 220.0).round() as u8;
     /*
     return (-646000.0 +
-                (646020.0 / (1.0 + ( -(dist_to_edge as f32 - SHADOW_W_PX as f32) / 349550000.0).powf(0.6474334) ) )
+                (646020.0 / (1.0 + ( -(dist_to_edge as f32 - shadow_w_px as f32) / 349550000.0).powf(0.6474334) ) )
     ).round() as u8;
     */
 }
 
-const SHADOW_W_PX: i32 = 24;
-const METAL_TEXTURE_OVLY: [u8; 16] = [
-    8,  12, 16, 12,
-    4,  8,  12,  8,
-    8,  4,  8,   4,
-    12, 8,  12,  8,
-];
 
-fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (u32, u32)) -> Result<(), Box<dyn std::error::Error>> {
-    use std::{cmp::min, io::Write};
+fn static_draw(screenshot_px: &[[u8; 4]], tmp: &mut File, items: &[dock::DockItem], dock_layout: &layout::DockLayout, icons: &mut icons::IconCache, theme: &theme::Theme) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
 
+    // The shelf is drawn into its band in dock space (see layout::Edge) and rotated; items are drawn in screen space
+    let (buf_x, space_h) = dock_layout.dock_space;
+    let buf_y = dock_layout.band_h;
     if buf_x < 12 || buf_y < 12 {
         return Ok(());
     }
 
     let mut buf = std::io::BufWriter::new(tmp);
 
+    let mut px_buf: Vec<[u8; 4]> = vec![[0, 0, 0, 0]; (buf_x * buf_y) as usize];
+
+    match (theme.skin_images.as_ref(), dock_layout.style) {
+        (Some(skin), _) => skin.draw_shelf(&mut canvas::Canvas::new(&mut px_buf, buf_x, buf_y), dock_layout),
+        (None, layout::DockStyle::Shelf) => draw_3d_shelf(&mut px_buf, screenshot_px, (buf_x, buf_y), theme),
+        (None, layout::DockStyle::Flat) => draw_2d_shelf(&mut canvas::Canvas::new(&mut px_buf, buf_x, buf_y), dock_layout, theme),
+    }
+
+    // Headroom for magnified icons stays transparent
+    let mut space_px = vec![[0, 0, 0, 0]; ((space_h - buf_y) * buf_x) as usize];
+    space_px.append(&mut px_buf);
+    let mut px_buf = dock_layout.edge.px_to_screen(&space_px, buf_x, space_h);
+    dock::draw_items(&mut canvas::Canvas::new(&mut px_buf, dock_layout.buf_w, dock_layout.buf_h), items, dock_layout, icons, theme);
+
+    // Final write to shared-memory buffer
+    // We hereby assume the end of one interior [u8;4] is next to the following [u8;4],
+    // allowing us to re-interpret px_buf as abuffer of bytes which may be written to the
+    // memory-mapped file.
+    let px_buff_bytes: &[u8] = unsafe { std::slice::from_raw_parts(px_buf.as_ptr() as *const u8, px_buf.len() * 4) };
+    buf.write_all(px_buff_bytes).map_err(err::eloc!())?;
+
+    buf.flush().map_err(err::eloc!())?;
+    Ok(())
+}
+
+// Tiger-style flat dock: translucent rounded rectangle, border, inner glow, no reflection
+fn draw_2d_shelf(canvas: &mut canvas::Canvas, dock_layout: &layout::DockLayout, theme: &theme::Theme) {
+    let shelf = dock_layout.shelf;
+    let flat = &theme.flat;
+    let shadow_w_px = theme.shadow.width_px;
+    let radius = flat.corner_radius as i32;
+    // Bottom corners sit on the screen edge, so only the top ones are rounded
+    let shelf_coverage = |x: i32, y: i32| canvas::rounded_rect_coverage(x - shelf.x, y - shelf.y, shelf.w, shelf.h + radius, radius);
+
+    // Drop shadow around the top and sides
+    for y in (shelf.y - shadow_w_px).max(0)..canvas.h {
+        for x in (shelf.x - shadow_w_px)..(shelf.x + shelf.w + shadow_w_px) {
+            if shelf_coverage(x, y) >= 1.0 {
+                continue;
+            }
+            let dx = (shelf.x - x).max(x - (shelf.x + shelf.w - 1)).max(0);
+            let dy = (shelf.y - y).max(0);
+            let dist = (((dx * dx) + (dy * dy)) as f32).sqrt() as i32;
+            if dist < shadow_w_px {
+                let shadow_a = 255 - shadow_falloff_i(dist, shadow_w_px);
+                canvas.blend(x, y, [0x00, 0x00, 0x00, shadow_a], flat.shadow_alpha);
+            }
+        }
+    }
+
+    let fill = flat.fill.bgra_premul();
+    let border = flat.border.bgra_premul();
+    let glow = flat.inner_glow.bgra_premul();
+    let glow_w = flat.glow_width_px.max(1) as f32;
+    for y in shelf.y..(shelf.y + shelf.h) {
+        for x in shelf.x..(shelf.x + shelf.w) {
+            let coverage = shelf_coverage(x, y);
+            if coverage <= 0.0 {
+                continue;
+            }
+            // Erase the shadow under the translucent body before filling it
+            canvas.px[((y * canvas.w) + x) as usize] = [0, 0, 0, 0];
+            canvas.blend(x, y, fill, coverage);
+
+            let dist_to_edge = (x - shelf.x).min(shelf.x + shelf.w - 1 - x).min(y - shelf.y);
+            if dist_to_edge == 0 || coverage < 1.0 {
+                canvas.blend(x, y, border, coverage);
+            }
+            else if (dist_to_edge as f32) < glow_w {
+                canvas.blend(x, y, glow, 1.0 - (dist_to_edge as f32 / glow_w));
+            }
+        }
+    }
+}
+
+// The procedural angled glass shelf, reflecting screenshot_px
+fn draw_3d_shelf(px_buf: &mut [[u8; 4]], screenshot_px: &[[u8; 4]], (buf_x, buf_y): (u32, u32), theme: &theme::Theme) {
+    use std::cmp::min;
+
     let dock_w = buf_x / 2;
     let dock_lr_margin = (buf_x - dock_w) / 2;
     let begin_x = dock_lr_margin;
@@ -368,15 +1618,15 @@ fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (
 
     // Compute dock detailed geometry
 
-    let dock_lip_h = 6;
-    let dock_angle_deg = 30;
+    let shadow_w_px = theme.shadow.width_px;
+    let dock_lip_h = theme.lip.height_px;
+    let dock_angle_deg = theme.shelf.angle_deg;
 
     // Used with: griffin-reader 'file_int_ex(45, "/tmp/a", lambda x: x-1)' 'file_int_ex(45, "/tmp/a", lambda x: x+1)'
     //let contents = std::fs::read_to_string("/tmp/a")?;
     //let dock_angle_deg = contents.parse::<i32>()?;
 
-    let dock_top_x_inset = f32::sin(dock_angle_deg as f32 * (180.0 as f32 / std::f32::consts::PI)) * buf_y as f32;
-    let dock_top_x_inset = dock_top_x_inset.abs();
+    let dock_top_x_inset = layout::shelf_top_x_inset(buf_y, dock_angle_deg);
     // let dock_height = f32::sin(dock_angle_deg as f32 * (180.0 as f32 / std::f32::consts::PI)) as u32;
 
     //eprintln!("dock_top_x_inset = {:?}", dock_top_x_inset);
@@ -389,15 +1639,8 @@ fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (
         );
     }
 
-    let mut px_buf: Vec<[u8; 4]> = Vec::with_capacity((buf_x * buf_y) as usize);
-    for y in 0..buf_y {
-        for x in 0..buf_x {
-            px_buf.push([0 as u8, 0 as u8, 0 as u8, 0 as u8]);
-        }
-    }
-
     for y in 0..buf_y {
-        let dist_to_y_edge = SHADOW_W_PX - y as i32;
+        let dist_to_y_edge = shadow_w_px - y as i32;
         for x in 0..begin_x {
             let buf_i = ((y * buf_x) + x) as usize;
             //buf.write_all(&[0 as u8, 0 as u8, 0 as u8, 0 as u8]).map_err(err::eloc!())?;
@@ -406,53 +1649,52 @@ fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (
         for x in begin_x..end_x {
             let buf_i = ((y * buf_x) + x) as usize;
             if x > dock_x_insets[y as usize] as u32 + begin_x as u32 && x < end_x - dock_x_insets[y as usize] as u32 {
-                // We are within the "dock" area - but we use the first interior SHADOW_W_PX as an alpha ramp-up from transparent to the actual edge.
+                // We are within the "dock" area - but we use the first interior shadow_w_px as an alpha ramp-up from transparent to the actual edge.
                 let dist_to_left_edge = (x as i32 - dock_x_insets[y as usize]) - dock_lr_margin as i32;
                 let dist_to_right_edge = (end_x as i32 - dock_x_insets[y as usize] as i32) - x as i32;
-                if dist_to_y_edge > 0 && dist_to_y_edge <= SHADOW_W_PX {
-                    // Make a linear shadow, skipping the first + last SHADOW_W_PX of X space
-                    if dist_to_left_edge < SHADOW_W_PX || dist_to_right_edge < SHADOW_W_PX {
+                if dist_to_y_edge > 0 && dist_to_y_edge <= shadow_w_px {
+                    // Make a linear shadow, skipping the first + last shadow_w_px of X space
+                    if dist_to_left_edge < shadow_w_px || dist_to_right_edge < shadow_w_px {
                         // Circular fall-off or some such shadow nonsense
-                        let dist_to_x_corner = SHADOW_W_PX - std::cmp::min(dist_to_left_edge, dist_to_right_edge);
+                        let dist_to_x_corner = shadow_w_px - std::cmp::min(dist_to_left_edge, dist_to_right_edge);
                         let dist_to_y_corner = dist_to_y_edge;
                         //let dist_to_corner = ((dist_to_x_corner*dist_to_x_corner) as f32 + (dist_to_y_corner*dist_to_y_corner) as f32).sqrt() as i32;
                         let dist_to_corner = ((dist_to_x_corner*dist_to_x_corner) as f32 + (dist_to_y_corner*dist_to_y_corner) as f32).sqrt() as i32;
-                        //let shadow_amnt = 255.0 - ((dist_to_corner as f32 / SHADOW_W_PX as f32) * 255.0);
-                        let shadow_amnt = 255 - shadow_falloff_i(dist_to_corner);
+                        //let shadow_amnt = 255.0 - ((dist_to_corner as f32 / shadow_w_px as f32) * 255.0);
+                        let shadow_amnt = 255 - shadow_falloff_i(dist_to_corner, shadow_w_px);
                         //buf.write_all(&[0x00 as u8, 0x00 as u8, 0x00 as u8, shadow_amnt]).map_err(err::eloc!())?;
                         px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, shadow_amnt];
                     }
                     else {
-                        let linear_shadow_a = 255 - shadow_falloff_i(dist_to_y_edge); //((1.0 - (dist_to_y_edge as f32 / SHADOW_W_PX as f32)) * 255.0) as u8;
+                        let linear_shadow_a = 255 - shadow_falloff_i(dist_to_y_edge, shadow_w_px); //((1.0 - (dist_to_y_edge as f32 / shadow_w_px as f32)) * 255.0) as u8;
                         //buf.write_all(&[0x00 as u8, 0x00 as u8, 0x00 as u8, linear_shadow_a]).map_err(err::eloc!())?;
                         px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, linear_shadow_a];
                     }
                 }
-                else if dist_to_left_edge < SHADOW_W_PX {
-                    let linear_shadow_a = shadow_falloff_i(dist_to_left_edge);
+                else if dist_to_left_edge < shadow_w_px {
+                    let linear_shadow_a = shadow_falloff_i(dist_to_left_edge, shadow_w_px);
                     //buf.write_all(&[0x00 as u8, 0x00 as u8, 0x00 as u8, linear_shadow_a]).map_err(err::eloc!())?;
                     px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, linear_shadow_a];
                 }
-                else if dist_to_left_edge == SHADOW_W_PX {
+                else if dist_to_left_edge == shadow_w_px {
                     //buf.write_all(&[0x00 as u8, 0x00 as u8, 0x00 as u8, 0xFF as u8]).map_err(err::eloc!())?;
                     px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, 0xFF as u8];
                 }
-                else if dist_to_right_edge < SHADOW_W_PX {
-                    let linear_shadow_a = shadow_falloff_i(dist_to_right_edge);
+                else if dist_to_right_edge < shadow_w_px {
+                    let linear_shadow_a = shadow_falloff_i(dist_to_right_edge, shadow_w_px);
 //                    buf.write_all(&[0x00 as u8, 0x00 as u8, 0x00 as u8, linear_shadow_a]).map_err(err::eloc!())?;
                     px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, linear_shadow_a];
                 }
-                else if dist_to_right_edge == SHADOW_W_PX {
+                else if dist_to_right_edge == shadow_w_px {
                     //buf.write_all(&[0x00 as u8, 0x00 as u8, 0x00 as u8, 0xFF as u8]).map_err(err::eloc!())?;
                     px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, 0xFF as u8];
                 }
                 else {
-                    let screenshot_reflected_y = (screenshot_y_above_dock_dist - y) + SHADOW_W_PX as u32; // todo more magic here
+                    let screenshot_reflected_y = (screenshot_y_above_dock_dist - y) + shadow_w_px as u32; // todo more magic here
                     let x_correction_amount = (dock_w / 2) + 6; // Ok genius where are we being offset by w/2 and six pixels?!/???
                     let screenshot_px_i = ((screenshot_reflected_y * dock_w) + x + x_correction_amount) as usize;
 
-                    //let metal_overlay_val = METAL_TEXTURE_OVLY[(((y % 4) * 4) + (x % 4)) as usize];
-                    let metal_overlay_val = 0;
+                    let metal_overlay_val = (theme.material.texture[(((y % 4) * 4) + (x % 4)) as usize] as f32 * theme.material.strength).min(255.0) as u8;
 
                     if screenshot_px_i > 0 && screenshot_px_i < screenshot_px.len() {
                         let mut b = screenshot_px[screenshot_px_i][0];
@@ -469,17 +1711,21 @@ fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (
                             r -= metal_overlay_val;
                         }
 
+                        let opacity = theme.reflection.opacity;
+                        let tint = theme.reflection.tint.bgr();
+                        let b = ((b as f32 * opacity) + (tint[0] as f32 * (1.0 - opacity))) as u8;
+                        let g = ((g as f32 * opacity) + (tint[1] as f32 * (1.0 - opacity))) as u8;
+                        let r = ((r as f32 * opacity) + (tint[2] as f32 * (1.0 - opacity))) as u8;
+
                         // buf.write_all(&[b, g, r, 0xFF as u8]).map_err(err::eloc!())?;
                         px_buf[buf_i] = [b, g, r, 0xFF as u8];
 
                     }
                     else {
-                        let a = 0xE0;
-                        let r = min(((buf_x - x) * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-                        let g = min((x * 0xFF) / buf_x, ((buf_y - y) * 0xFF) / buf_y);
-                        let b = min(((buf_x - x) * 0xFF) / buf_x, (y * 0xFF) / buf_y);
+                        let a = theme.shelf.fallback_alpha;
+                        let [b, g, r] = theme.shelf.fallback_gradient.sample(x, y, buf_x, buf_y);
                         //buf.write_all(&[b as u8, g as u8, r as u8, a as u8]).map_err(err::eloc!())?;
-                        px_buf[buf_i] = [b as u8, g as u8, r as u8, a as u8];
+                        px_buf[buf_i] = [b, g, r, a];
                     }
                 }
             }
@@ -497,7 +1743,7 @@ fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (
 
     // Blur the shadows by re-processing & avreaging a 2x2 grid
     for y in 0..buf_y {
-        let dist_to_y_edge = SHADOW_W_PX - y as i32;
+        let dist_to_y_edge = shadow_w_px - y as i32;
         for x in begin_x..end_x {
             let buf_i = ((y * buf_x) + x) as usize;
 
@@ -513,45 +1759,43 @@ fn static_draw(screenshot_px: &Vec::<[u8; 4]>, tmp: &mut File, (buf_x, buf_y): (
             assert!(buf_i_east < px_buf.len());
 
             if x > dock_x_insets[y as usize] as u32 + begin_x as u32 && x < end_x - dock_x_insets[y as usize] as u32 {
-                // We are within the "dock" area - but we use the first interior SHADOW_W_PX as an alpha ramp-up from transparent to the actual edge.
+                // We are within the "dock" area - but we use the first interior shadow_w_px as an alpha ramp-up from transparent to the actual edge.
                 let dist_to_left_edge = (x as i32 - dock_x_insets[y as usize]) - dock_lr_margin as i32;
                 let dist_to_right_edge = (end_x as i32 - dock_x_insets[y as usize] as i32) - x as i32;
-                if dist_to_y_edge > 0 && dist_to_y_edge <= SHADOW_W_PX {
-                    // Make a linear shadow, skipping the first + last SHADOW_W_PX of X space
-                    if dist_to_left_edge < SHADOW_W_PX || dist_to_right_edge < SHADOW_W_PX {
+                if dist_to_y_edge > 0 && dist_to_y_edge <= shadow_w_px {
+                    // Make a linear shadow, skipping the first + last shadow_w_px of X space
+                    if dist_to_left_edge < shadow_w_px || dist_to_right_edge < shadow_w_px {
                         px_buf[buf_i][3] = ((px_buf[buf_i_north][3] as i32 + px_buf[buf_i_south][3] as i32 + px_buf[buf_i_west][3] as i32 + px_buf[buf_i_east][3] as i32) / 4) as u8;
                     }
                     else {
                         px_buf[buf_i][3] = ((px_buf[buf_i_north][3] as i32 + px_buf[buf_i_south][3] as i32 + px_buf[buf_i_west][3] as i32 + px_buf[buf_i_east][3] as i32) / 4) as u8;
                     }
                 }
-                else if dist_to_left_edge < SHADOW_W_PX {
+                else if dist_to_left_edge < shadow_w_px {
                     px_buf[buf_i][3] = ((px_buf[buf_i_north][3] as i32 + px_buf[buf_i_south][3] as i32 + px_buf[buf_i_west][3] as i32 + px_buf[buf_i_east][3] as i32) / 4) as u8;
                 }
-                else if dist_to_left_edge == SHADOW_W_PX {
+                else if dist_to_left_edge == shadow_w_px {
                     //px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, 0xFF as u8];
                 }
-                else if dist_to_right_edge < SHADOW_W_PX {
+                else if dist_to_right_edge < shadow_w_px {
                     px_buf[buf_i][3] = ((px_buf[buf_i_north][3] as i32 + px_buf[buf_i_south][3] as i32 + px_buf[buf_i_west][3] as i32 + px_buf[buf_i_east][3] as i32) / 4) as u8;
                 }
-                else if dist_to_right_edge == SHADOW_W_PX {
+                else if dist_to_right_edge == shadow_w_px {
                     //px_buf[buf_i] = [0x00 as u8, 0x00 as u8, 0x00 as u8, 0xFF as u8];
                 }
             }
         }
     }
 
-
-
-    // Final write to shared-memory buffer
-    // We hereby assume the end of one interior [u8;4] is next to the following [u8;4],
-    // allowing us to re-interpret px_buf as abuffer of bytes which may be written to the
-    // memory-mapped file.
-    let px_buff_bytes: &[u8] = unsafe { std::slice::from_raw_parts(px_buf.as_ptr() as *const u8, px_buf.len() * 4) };
-    buf.write_all(&px_buff_bytes).map_err(err::eloc!())?;
-
-    buf.flush().map_err(err::eloc!())?;
-    Ok(())
+    // Lip: the lit front edge along the bottom of the shelf
+    let lip_color = theme.lip.color.bgra_premul();
+    let mut canvas = canvas::Canvas::new(px_buf, buf_x, buf_y);
+    for y in buf_y.saturating_sub(dock_lip_h)..buf_y {
+        let inset = dock_x_insets[y as usize] + shadow_w_px;
+        for x in (begin_x as i32 + inset)..(end_x as i32 - inset) {
+            canvas.blend(x, y as i32, lip_color, 1.0);
+        }
+    }
 }
 
 
@@ -663,11 +1907,16 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
         event: wl_keyboard::Event,
         _: &(),
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
     ) {
-        if let wl_keyboard::Event::Key { key, .. } = event {
-            if key == 1 {
-                // ESC key
+        if let wl_keyboard::Event::Key { key, state: WEnum::Value(wl_keyboard::KeyState::Pressed), .. } = event {
+            if state.menu.is_some() {
+                state.on_menu_key(key, qh);
+            }
+            else if state.stack.is_some() {
+                state.on_stack_key(key);
+            }
+            else if key == KEY_ESC {
                 state.running = false;
             }
         }
@@ -675,6 +1924,35 @@ impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
     }
 }
 
+// From linux/input-event-codes.h
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const KEY_ESC: u32 = 1;
+const KEY_ENTER: u32 = 28;
+const KEY_SPACE: u32 = 57;
+const KEY_KPENTER: u32 = 96;
+const KEY_HOME: u32 = 102;
+const KEY_UP: u32 = 103;
+const KEY_END: u32 = 107;
+const KEY_DOWN: u32 = 108;
+// One mouse wheel notch, in wl_pointer axis units
+const SCROLL_STEP: f64 = 10.0;
+
+const MAGNIFY_IN_MS: u32 = 120;
+const MAGNIFY_OUT_MS: u32 = 220;
+const MAGNIFY_STIFFNESS: f32 = 900.0;
+
+const BOUNCE_HOP_MS: u32 = 600;
+// Launches give up after ~12s, in case the app never opens a window we can match
+const LAUNCH_BOUNCE_HOPS: u32 = 20;
+const ATTENTION_BOUNCE_HOPS: u32 = 10;
+
+const DROP_GAP_MS: u32 = 180;
+const POOF_MS: u32 = 450;
+
+const RECENT_FILES_IN_MENU: usize = 10;
+
 impl Dispatch<wl_pointer::WlPointer, ()> for State {
     fn event(
         state: &mut Self,
@@ -682,9 +1960,73 @@ impl Dispatch<wl_pointer::WlPointer, ()> for State {
         event: wl_pointer::Event,
         _: &(),
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
     ) {
-        if let wl_pointer::Event::Motion { .. } = event {
+        // Popups get their own events; everything below is about the dock surface
+        match &event {
+            wl_pointer::Event::Enter { surface, surface_x, surface_y, .. } if state.base_surface.as_ref() != Some(surface) => {
+                state.pointer_popup = state.popups.by_surface(surface).map(|id| (id, (*surface_x, *surface_y)));
+                if state.pointer_popup.is_some_and(|(id, _)| state.menu.as_ref().map(|m| m.popup) == Some(id)) {
+                    state.on_menu_pointer(Some(*surface_y), qh);
+                }
+                if state.pointer_popup.is_some_and(|(id, _)| state.stack.as_ref().and_then(|s| s.popup) == Some(id)) {
+                    state.on_stack_pointer(Some((*surface_x, *surface_y)), qh);
+                }
+                return;
+            }
+            wl_pointer::Event::Leave { .. } | wl_pointer::Event::Motion { .. } | wl_pointer::Event::Button { .. } | wl_pointer::Event::Axis { .. }
+                if state.pointer_popup.is_some() => {
+                let over_menu = state.pointer_popup.is_some_and(|(id, _)| state.menu.as_ref().map(|m| m.popup) == Some(id));
+                let over_stack = state.pointer_popup.is_some_and(|(id, _)| state.stack.as_ref().and_then(|s| s.popup) == Some(id));
+                match event {
+                    wl_pointer::Event::Leave { .. } => {
+                        state.pointer_popup = None;
+                        if over_menu {
+                            state.on_menu_pointer(None, qh);
+                        }
+                        if over_stack {
+                            state.on_stack_pointer(None, qh);
+                            state.scroll_accum = 0.0;
+                        }
+                    }
+                    wl_pointer::Event::Motion { surface_x, surface_y, .. } => {
+                        if let Some((_, pos)) = state.pointer_popup.as_mut() {
+                            *pos = (surface_x, surface_y);
+                        }
+                        if over_menu {
+                            state.on_menu_pointer(Some(surface_y), qh);
+                        }
+                        if over_stack {
+                            state.on_stack_pointer(Some((surface_x, surface_y)), qh);
+                        }
+                    }
+                    wl_pointer::Event::Button { serial, state: button_state, .. } => {
+                        state.last_input_serial = serial;
+                        // Press-drag-release from the dock icon works as well as two clicks
+                        if over_menu && button_state == WEnum::Value(wl_pointer::ButtonState::Released) {
+                            state.pick_menu_row();
+                        }
+                        if over_stack && button_state == WEnum::Value(wl_pointer::ButtonState::Released) {
+                            state.pick_stack_entry();
+                        }
+                    }
+                    wl_pointer::Event::Axis { axis: WEnum::Value(wl_pointer::Axis::VerticalScroll), value, .. } if over_stack => {
+                        state.on_stack_scroll(value, qh);
+                    }
+                    _ => { }
+                }
+                return;
+            }
+            _ => { }
+        }
+
+        if let wl_pointer::Event::Motion { surface_x, surface_y, .. } = event {
+            state.pointer_pos = Some((surface_x, surface_y));
+            state.magnify_follow(surface_x, surface_y);
+            state.drag_motion((surface_x, surface_y), qh);
+            if state.drag.as_ref().is_some_and(|d| d.active) {
+                state.close_stack(); // Dragging the folder itself away
+            }
             let now_ms = std::time::SystemTime::now()
                             .duration_since(std::time::UNIX_EPOCH)
                             .unwrap()
@@ -695,6 +2037,76 @@ impl Dispatch<wl_pointer::WlPointer, ()> for State {
         }
         else {
             eprintln!("Got Dispatch<wl_pointer::WlPointer, ()> {:?}", event);
+            match event {
+                wl_pointer::Event::Enter { surface_x, surface_y, .. } => {
+                    state.pointer_pos = Some((surface_x, surface_y));
+                    state.magnify_follow(surface_x, surface_y);
+                    state.anims.tween(anim::AnimKey::MagnifyAmount, 0.0, 1.0, MAGNIFY_IN_MS, anim::Easing::EaseOutCubic);
+                }
+                wl_pointer::Event::Leave { .. } => {
+                    // A drag in progress holds the pointer until release; this is a press that never became one
+                    if state.drag.as_ref().is_some_and(|d| !d.active) {
+                        state.drag = None;
+                    }
+                    state.pointer_pos = None;
+                    state.scroll_accum = 0.0;
+                    state.anims.tween(anim::AnimKey::MagnifyAmount, 0.0, 0.0, MAGNIFY_OUT_MS, anim::Easing::EaseInOutCubic);
+                    state.redraw_necessary = true;
+                }
+                wl_pointer::Event::Button { serial, button, state: WEnum::Value(wl_pointer::ButtonState::Released), .. } => {
+                    state.last_input_serial = serial;
+                    if button == BTN_LEFT && let Some(drag) = state.drag.take() && drag.active {
+                        state.finish_drag(drag, qh);
+                        return;
+                    }
+                    // Act on release like every other dock; press is reserved for dragging
+                    if let Some(i) = state.item_under_pointer() {
+                        let is_folder = matches!(state.dock.items[i].kind, dock::ItemKind::Folder(_));
+                        let stack_open = state.stack.as_ref().is_some_and(|s| s.item_id == state.dock.items[i].id());
+                        match button {
+                            // A second click on the folder closes its stack again
+                            BTN_LEFT if is_folder && stack_open => state.close_stack(),
+                            BTN_LEFT if is_folder => state.open_stack(i, qh),
+                            BTN_LEFT => state.click_item(i),
+                            BTN_MIDDLE => state.launch_item(i), // Always a fresh instance
+                            _ => { }
+                        }
+                    }
+                }
+                wl_pointer::Event::Button { serial, button, state: WEnum::Value(wl_pointer::ButtonState::Pressed), .. } => {
+                    state.last_input_serial = serial;
+                    // Menus open on press, so the grab starts while the button is still held
+                    state.close_menu();
+                    // A press on the stack's own folder leaves it for the release to close
+                    let on_stack_item = state.item_under_pointer()
+                        .is_some_and(|i| state.stack.as_ref().is_some_and(|s| s.item_id == state.dock.items[i].id()));
+                    if !on_stack_item {
+                        state.close_stack();
+                    }
+                    if let Some(i) = state.item_under_pointer() && let Some((x, y)) = state.pointer_pos {
+                        match button {
+                            BTN_LEFT => state.drag = Some(drag::Drag::new(state.dock.items[i].id(), (x, y))),
+                            BTN_RIGHT => state.open_menu(i, qh),
+                            _ => { }
+                        }
+                    }
+                }
+                wl_pointer::Event::Button { serial, .. } => {
+                    state.last_input_serial = serial;
+                }
+                wl_pointer::Event::Axis { axis: WEnum::Value(wl_pointer::Axis::VerticalScroll), value, .. } => {
+                    if let Some(i) = state.item_under_pointer() {
+                        // Touchpads send many small deltas; only cycle once per notch's worth
+                        state.scroll_accum += value;
+                        if state.scroll_accum.abs() >= SCROLL_STEP {
+                            let direction = if state.scroll_accum > 0.0 { 1 } else { -1 };
+                            state.scroll_accum = 0.0;
+                            state.cycle_item_windows(i, direction);
+                        }
+                    }
+                }
+                _ => { }
+            }
         }
     }
 }
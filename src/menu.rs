@@ -0,0 +1,269 @@
+
+use crate::canvas::{rounded_rect_coverage, Canvas, Image};
+use crate::desktop::DesktopEntry;
+use crate::dock::{DockItem, ItemKind};
+use crate::popup::PopupId;
+use crate::recent::RecentFile;
+use crate::stack::{StackSort, StackView};
+use crate::text::TextRenderer;
+use crate::theme::{MenuTheme, Theme};
+use crate::toplevel::{Handle, Toplevels};
+
+// What picking a row does; carried out by State::run_menu_action
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuAction {
+    FocusWindow(Handle),
+    // Launch the app on this document
+    OpenRecent(String),
+    // Id of one of the item's [Desktop Action x] groups
+    DesktopAction(String),
+    Pin,
+    Unpin,
+    ToggleAutostart,
+    ShowInFileManager,
+    // How a folder's stack opens
+    SetStackView(StackView),
+    SetStackSort(StackSort),
+    // What a click does; for items where that is not obvious, like the trash
+    Open,
+    EmptyTrash,
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MenuRow {
+    Entry {
+        label: String,
+        action: MenuAction,
+        enabled: bool,
+        // Drawn with a dot in the left gutter, eg the focused window or "Open at Login"
+        checked: bool,
+    },
+    // Names the group below it; never selectable
+    Heading(String),
+    Separator,
+}
+
+impl MenuRow {
+    fn entry(label: &str, action: MenuAction, enabled: bool, checked: bool) -> MenuRow {
+        MenuRow::Entry { label: label.to_string(), action, enabled, checked }
+    }
+
+    fn is_enabled(&self) -> bool {
+        matches!(self, MenuRow::Entry { enabled: true, .. })
+    }
+}
+
+// An open right-click menu. Rows are a snapshot taken when it opened.
+pub struct Menu {
+    // DockItem::id() of the item it belongs to; indexes shift while a menu is open
+    pub item_id: String,
+    pub rows: Vec<MenuRow>,
+    pub selected: Option<usize>,
+    pub popup: PopupId,
+}
+
+impl Menu {
+    // The enabled row under y (popup surface coordinates)
+    pub fn row_at(&self, y: f64, theme: &Theme) -> Option<usize> {
+        let mut top = theme.menu.padding_px as f64;
+        for (i, row) in self.rows.iter().enumerate() {
+            let h = row_height(row, theme) as f64;
+            if y >= top && y < top + h {
+                return if row.is_enabled() { Some(i) } else { None };
+            }
+            top += h;
+        }
+        None
+    }
+
+    // Move the selection to the next enabled row in `direction`, wrapping around
+    pub fn step(&mut self, direction: i32) {
+        let n = self.rows.len() as i32;
+        if n == 0 {
+            return;
+        }
+        let start = match self.selected {
+            Some(i) => i as i32,
+            None if direction > 0 => -1,
+            None => n,
+        };
+        for offset in 1..=n {
+            let i = (start + (direction.signum() * offset)).rem_euclid(n) as usize;
+            if self.rows[i].is_enabled() {
+                self.selected = Some(i);
+                return;
+            }
+        }
+    }
+
+    pub fn select_first(&mut self) {
+        self.selected = None;
+        self.step(1);
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = None;
+        self.step(-1);
+    }
+
+    pub fn selected_action(&self) -> Option<MenuAction> {
+        match self.rows.get(self.selected?)? {
+            MenuRow::Entry { action, enabled: true, .. } => Some(action.clone()),
+            _ => None,
+        }
+    }
+}
+
+// Recent documents, windows, the desktop entry's actions, stack options for folders, dock options, then Quit.
+// The trash has its own short menu.
+pub fn item_rows(item: &DockItem, toplevels: &Toplevels, entry: Option<&DesktopEntry>, recent: &[RecentFile]) -> Vec<MenuRow> {
+    if item.kind == ItemKind::Trash {
+        return vec![
+            MenuRow::entry("Open", MenuAction::Open, true, false),
+            MenuRow::entry("Empty Trash", MenuAction::EmptyTrash, !crate::trash::is_empty(), false),
+            MenuRow::Separator,
+            MenuRow::entry("Remove from Dock", MenuAction::Unpin, true, false),
+        ];
+    }
+    let control = toplevels.supports_control();
+    let windows = item.windows(toplevels);
+    let mut groups: Vec<Vec<MenuRow>> = vec![];
+
+    let accepts_files = item.exec.as_deref().map(crate::desktop::exec_accepts_files).unwrap_or(false);
+    if accepts_files && !recent.is_empty() {
+        let mut rows = vec![MenuRow::Heading("Recent Documents".to_string())];
+        rows.extend(recent.iter().map(|file| MenuRow::entry(&file.name, MenuAction::OpenRecent(file.uri.clone()), true, false)));
+        groups.push(rows);
+    }
+
+    groups.push(windows.iter().map(|t| {
+        let title = if t.title.is_empty() { &item.name } else { &t.title };
+        MenuRow::entry(title, MenuAction::FocusWindow(t.handle.clone()), control, t.activated)
+    }).collect());
+
+    groups.push(item.actions.iter().map(|a| {
+        MenuRow::entry(&a.name, MenuAction::DesktopAction(a.id.clone()), a.exec.is_some(), false)
+    }).collect());
+
+    if let ItemKind::Folder(_) = item.kind {
+        let view = |label, view| MenuRow::entry(label, MenuAction::SetStackView(view), true, item.stack.view == view);
+        groups.push(vec![
+            MenuRow::Heading("View as".to_string()),
+            view("Automatic", StackView::Auto),
+            view("Fan", StackView::Fan),
+            view("Grid", StackView::Grid),
+        ]);
+        let sort = |label, sort| MenuRow::entry(label, MenuAction::SetStackSort(sort), true, item.stack.sort == sort);
+        groups.push(vec![
+            MenuRow::Heading("Sort by".to_string()),
+            sort("Name", StackSort::Name),
+            sort("Date Modified", StackSort::Date),
+        ]);
+    }
+
+    let mut options = vec![];
+    if item.pinned {
+        options.push(MenuRow::entry("Remove from Dock", MenuAction::Unpin, true, false));
+    }
+    else {
+        options.push(MenuRow::entry("Keep in Dock", MenuAction::Pin, true, false));
+    }
+    if let Some(entry) = entry {
+        options.push(MenuRow::entry("Open at Login", MenuAction::ToggleAutostart, true, crate::desktop::is_autostart(&entry.id)));
+    }
+    let in_file_manager = entry.is_some() || matches!(item.kind, ItemKind::Folder(_));
+    options.push(MenuRow::entry("Show in File Manager", MenuAction::ShowInFileManager, in_file_manager, false));
+    groups.push(options);
+
+    groups.push(vec![MenuRow::entry("Quit", MenuAction::Quit, control && !windows.is_empty(), false)]);
+
+    let mut rows = vec![];
+    for group in groups.into_iter().filter(|g| !g.is_empty()) {
+        if !rows.is_empty() {
+            rows.push(MenuRow::Separator);
+        }
+        rows.extend(group);
+    }
+    rows
+}
+
+fn row_height(row: &MenuRow, theme: &Theme) -> i32 {
+    match row {
+        MenuRow::Entry { .. } | MenuRow::Heading(_) => theme.menu.row_height_px as i32,
+        MenuRow::Separator => (theme.menu.row_height_px / 2) as i32,
+    }
+}
+
+// The menu's rounded background with its 1px border, filling the canvas; folder stacks use it too
+pub fn draw_panel(canvas: &mut Canvas, w: i32, h: i32, style: &MenuTheme) {
+    let radius = style.corner_radius as i32;
+    canvas.fill_rounded_rect(0, 0, w, h, radius, style.background.bgra_premul());
+    // The outer shape minus the shape inset by one pixel; both are 0.0 outside themselves,
+    // so the last row and column get their border too
+    for y in 0..h {
+        for x in 0..w {
+            let outer = rounded_rect_coverage(x, y, w, h, radius);
+            let inner = rounded_rect_coverage(x - 1, y - 1, w - 2, h - 2, (radius - 1).max(0));
+            if outer - inner > 0.0 {
+                canvas.blend(x, y, style.border.bgra_premul(), outer - inner);
+            }
+        }
+    }
+}
+
+// The whole menu as one image; its size depends only on the rows, so changing the
+// selection can reuse the popup
+pub fn render(rows: &[MenuRow], selected: Option<usize>, text: &TextRenderer, theme: &Theme) -> Image {
+    let style = &theme.menu;
+    let pad = style.padding_px as i32;
+    let row_h = style.row_height_px as i32;
+    let gutter = (row_h * 4) / 5; // Room for the check dot
+    let right_margin = (row_h * 3) / 5;
+
+    let labels: Vec<Option<(Image, Image)>> = rows.iter().map(|row| match row {
+        MenuRow::Entry { label, enabled, .. } => {
+            let color = if *enabled { style.color } else { style.disabled_color };
+            Some((text.render(label, style.size_px, color.bgra_premul()),
+                  text.render(label, style.size_px, style.highlight_color.bgra_premul())))
+        }
+        MenuRow::Heading(label) => Some((text.render(label, style.size_px, style.disabled_color.bgra_premul()), Image::default())),
+        MenuRow::Separator => None,
+    }).collect();
+    let text_w = labels.iter().flatten().map(|(img, _)| img.w as i32).max().unwrap_or(0);
+    let w = (gutter + text_w + right_margin + (2 * pad)).max(style.min_width_px as i32);
+    let h = (2 * pad) + rows.iter().map(|row| row_height(row, theme)).sum::<i32>();
+
+    let mut img = Image::new(w as u32, h as u32);
+    let mut canvas = Canvas::new(&mut img.px, w as u32, h as u32);
+    let radius = style.corner_radius as i32;
+    draw_panel(&mut canvas, w, h, style);
+
+    let mut top = pad;
+    for (i, (row, label)) in rows.iter().zip(labels.iter()).enumerate() {
+        let rh = row_height(row, theme);
+        match (row, label) {
+            (MenuRow::Entry { checked, .. }, Some((normal, highlighted))) => {
+                let is_selected = selected == Some(i) && row.is_enabled();
+                if is_selected {
+                    canvas.fill_rounded_rect(pad, top, w - (2 * pad), rh, (radius - pad).max(3), style.highlight.bgra_premul());
+                }
+                let label = if is_selected { highlighted } else { normal };
+                if *checked {
+                    let dot = (row_h / 5).max(4);
+                    let color = if is_selected { style.highlight_color } else { style.color };
+                    canvas.fill_rounded_rect(pad + ((gutter - dot) / 2), top + ((rh - dot) / 2), dot, dot, dot / 2, color.bgra_premul());
+                }
+                canvas.blit(label, pad + gutter, top + ((rh - label.h as i32) / 2), 1.0);
+            }
+            (MenuRow::Heading(_), Some((label, _))) => {
+                canvas.blit(label, pad + (gutter / 2), top + ((rh - label.h as i32) / 2), 1.0);
+            }
+            _ => {
+                canvas.fill_rounded_rect(pad + (gutter / 2), top + (rh / 2), w - (2 * pad) - gutter, 1, 0, style.separator.bgra_premul());
+            }
+        }
+        top += rh;
+    }
+    img
+}
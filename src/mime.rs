@@ -0,0 +1,166 @@
+
+use std::collections::HashMap;
+use std::path::Path;
+
+// File types from the shared-mime-info database that update-mime-database writes to
+// <data dir>/mime; see https://specifications.freedesktop.org/shared-mime-info-spec/latest/
+// Only file names are used to guess types, never contents.
+
+#[derive(Debug, Default)]
+pub struct MimeDb {
+    // Globs flagged "cs", such as "*.C" for C++ (which must not also match "*.c")
+    case_sensitive: Globs,
+    // Everything else, lowercased
+    globs: Globs,
+    // Type -> the types it is a kind of, eg text/x-csrc -> text/plain
+    parents: HashMap<String, Vec<String>>,
+    // Old name -> canonical name
+    aliases: HashMap<String, String>,
+    // Type -> icon name, for the few types not named the usual way
+    icons: HashMap<String, String>,
+    // Type -> fallback icon name, eg image/png -> image-x-generic
+    generic_icons: HashMap<String, String>,
+}
+
+// The simple globs, which are nearly all of them, as (weight, data dir load order, type)
+#[derive(Debug, Default)]
+struct Globs {
+    // "*.tar.gz" -> "tar.gz"
+    suffixes: HashMap<String, (u32, usize, String)>,
+    // Whole file names such as "Makefile"
+    literals: HashMap<String, (u32, usize, String)>,
+}
+
+impl Globs {
+    fn lookup(&self, name: &str) -> Option<&String> {
+        if let Some((_, _, mime)) = self.literals.get(name) {
+            return Some(mime);
+        }
+        // Longest suffix first, so "x.tar.gz" is a compressed tarball rather than just gzip
+        name.match_indices('.').find_map(|(i, _)| self.suffixes.get(&name[i + 1..]).map(|(_, _, mime)| mime))
+    }
+}
+
+impl MimeDb {
+    // Earlier data dirs (ie the user's own) take priority
+    pub fn load() -> MimeDb {
+        let mut db = MimeDb::default();
+        for (order, dir) in crate::desktop::data_dirs().iter().rev().enumerate() {
+            let mime_dir = dir.join("mime");
+            if let Ok(globs) = std::fs::read_to_string(mime_dir.join("globs2")) {
+                db.load_globs(&globs, order);
+            }
+            if let Ok(subclasses) = std::fs::read_to_string(mime_dir.join("subclasses")) {
+                for (child, parent) in pairs(&subclasses, ' ') {
+                    db.parents.entry(child).or_default().push(parent);
+                }
+            }
+            if let Ok(aliases) = std::fs::read_to_string(mime_dir.join("aliases")) {
+                db.aliases.extend(pairs(&aliases, ' '));
+            }
+            if let Ok(icons) = std::fs::read_to_string(mime_dir.join("icons")) {
+                db.icons.extend(pairs(&icons, ':'));
+            }
+            if let Ok(generic_icons) = std::fs::read_to_string(mime_dir.join("generic-icons")) {
+                db.generic_icons.extend(pairs(&generic_icons, ':'));
+            }
+        }
+        db
+    }
+
+    // Lines are "weight:type:glob[:flags]". `order` counts up as data dirs gain priority.
+    fn load_globs(&mut self, globs: &str, order: usize) {
+        for line in globs.lines().filter(|l| !l.starts_with('#')) {
+            let mut fields = line.split(':');
+            let (weight, mime, glob) = match (fields.next().and_then(|w| w.parse::<u32>().ok()), fields.next(), fields.next()) {
+                (Some(weight), Some(mime), Some(glob)) => (weight, mime.to_string(), glob),
+                _ => continue,
+            };
+            let (globs, glob) = match fields.next().is_some_and(|flags| flags.split(',').any(|f| f == "cs")) {
+                true => (&mut self.case_sensitive, glob.to_string()),
+                false => (&mut self.globs, glob.to_lowercase()),
+            };
+            let (map, key) = match glob.strip_prefix("*.") {
+                Some(suffix) if !suffix.contains(['*', '?', '[']) => (&mut globs.suffixes, suffix.to_string()),
+                None if !glob.contains(['*', '?', '[']) => (&mut globs.literals, glob),
+                _ => continue, // Fancier globs are rare enough to do without
+            };
+            // Ties go to the later, higher priority data dir, but within one file to the first entry
+            match map.get(&key) {
+                Some((existing, existing_order, _)) if *existing > weight || (*existing == weight && *existing_order == order) => { }
+                _ => {
+                    map.insert(key, (weight, order, mime));
+                }
+            }
+        }
+    }
+
+    pub fn for_path(&self, path: &Path) -> String {
+        if path.is_dir() {
+            return "inode/directory".to_string();
+        }
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        self.case_sensitive.lookup(&name)
+            .or_else(|| self.globs.lookup(&name.to_lowercase()))
+            .cloned()
+            .unwrap_or_else(|| "application/octet-stream".to_string())
+    }
+
+    // Local files by name; anything else by its scheme, the way desktop files list URL handlers
+    pub fn for_uri(&self, uri: &str) -> String {
+        match crate::desktop::uri_to_path(uri) {
+            Some(path) => self.for_path(&path),
+            None => format!("x-scheme-handler/{}", uri.split(':').next().unwrap_or("").to_lowercase()),
+        }
+    }
+
+    // Icon theme names to try for a type, best first: image/png -> image-png, image-x-generic
+    pub fn icon_names(&self, mime: &str) -> Vec<String> {
+        if mime == "inode/directory" {
+            return vec!["folder".to_string()];
+        }
+        let media = mime.split('/').next().unwrap_or("application");
+        vec![
+            self.icons.get(mime).cloned().unwrap_or_else(|| mime.replace('/', "-")),
+            self.generic_icons.get(mime).cloned().unwrap_or_else(|| format!("{}-x-generic", media)),
+            "text-x-generic".to_string(),
+        ]
+    }
+
+    // Is `mime` a kind of `wanted`? `wanted` may be a "type/*" wildcard.
+    pub fn is_a(&self, mime: &str, wanted: &str) -> bool {
+        let canonical = |m: &str| self.aliases.get(m).cloned().unwrap_or_else(|| m.to_string());
+        let wanted = canonical(wanted);
+        let mut queue = vec![canonical(mime)];
+        let mut seen: Vec<String> = vec![];
+        while let Some(current) = queue.pop() {
+            let matches = match wanted.strip_suffix("/*") {
+                Some(media) => current.split('/').next() == Some(media),
+                None => current == wanted,
+            };
+            if matches {
+                return true;
+            }
+            if seen.contains(&current) {
+                continue;
+            }
+            // Implicit parents from the spec: text is plain text, and any file is a stream of bytes
+            if current.starts_with("text/") && current != "text/plain" {
+                queue.push("text/plain".to_string());
+            }
+            if !current.starts_with("inode/") && !current.starts_with("x-scheme-handler/") && current != "application/octet-stream" {
+                queue.push("application/octet-stream".to_string());
+            }
+            queue.extend(self.parents.get(&current).into_iter().flatten().cloned());
+            seen.push(current);
+        }
+        false
+    }
+}
+
+fn pairs(contents: &str, separator: char) -> impl Iterator<Item = (String, String)> + '_ {
+    contents.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(move |l| l.split_once(separator))
+        .map(|(a, b)| (a.trim().to_string(), b.trim().to_string()))
+}
@@ -0,0 +1,276 @@
+
+use std::io::Write;
+
+use wayland_client::protocol::{wl_buffer, wl_compositor, wl_seat, wl_shm, wl_surface};
+use wayland_client::{Connection, Dispatch, QueueHandle};
+use wayland_protocols::xdg::shell::client::{xdg_popup, xdg_positioner, xdg_surface, xdg_wm_base};
+
+use crate::canvas::Image;
+use crate::layout::{Edge, Rect};
+use crate::State;
+
+// Tooltips and menus live in xdg_popup surfaces of their own, so they can reach past the
+// dock's buffer and the compositor can keep them on screen.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PopupId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopupKind {
+    // Never takes input; pointer events go to whatever is underneath
+    Tooltip,
+    // Grabs the pointer and keyboard; the compositor dismisses it on an outside click
+    Menu,
+}
+
+// Where a popup goes: beside `rect` (dock surface coordinates) on the side away from `edge`,
+// or centered on a point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    pub rect: Rect,
+    pub edge: Edge,
+    pub gap: i32,
+    // Offset from the middle of `rect` to the popup's center; when set, edge and gap are unused
+    pub center_offset: Option<(i32, i32)>,
+}
+
+impl Placement {
+    pub fn beside(rect: Rect, edge: Edge, gap: i32) -> Placement {
+        Placement { rect, edge, gap, center_offset: None }
+    }
+
+    // Centered on (x, y), which may be outside the dock surface (w, h), eg under a dragging
+    // pointer. The anchor itself has to stay on the surface, so it is clamped there.
+    pub fn centered_on((x, y): (i32, i32), (w, h): (i32, i32)) -> Placement {
+        let (ax, ay) = (x.clamp(0, (w - 1).max(0)), y.clamp(0, (h - 1).max(0)));
+        Placement {
+            rect: Rect { x: ax, y: ay, w: 1, h: 1 },
+            edge: Edge::default(),
+            gap: 0,
+            center_offset: Some((x - ax, y - ay)),
+        }
+    }
+}
+
+pub struct Popup {
+    pub id: PopupId,
+    pub kind: PopupKind,
+    pub surface: wl_surface::WlSurface,
+    xdg_surface: xdg_surface::XdgSurface,
+    xdg_popup: xdg_popup::XdgPopup,
+    pub placement: Placement,
+    pub content: Image,
+    configured: bool,
+    buffer: Option<wl_buffer::WlBuffer>,
+}
+
+#[derive(Default)]
+pub struct Popups {
+    pub compositor: Option<wl_compositor::WlCompositor>,
+    pub wm_base: Option<xdg_wm_base::XdgWmBase>,
+    pub shm: Option<wl_shm::WlShm>,
+    pub list: Vec<Popup>,
+    next_id: u32,
+}
+
+impl Popups {
+    // `grab` is the seat and serial of the input event that opened the popup; menus need one
+    pub fn open(&mut self, kind: PopupKind, content: Image, placement: Placement, parent: &xdg_surface::XdgSurface,
+                grab: Option<(&wl_seat::WlSeat, u32)>, qh: &QueueHandle<State>) -> Option<PopupId> {
+        let (compositor, wm_base) = match (self.compositor.as_ref(), self.wm_base.as_ref()) {
+            (Some(compositor), Some(wm_base)) => (compositor, wm_base),
+            _ => {
+                eprintln!("{}:{} no wl_compositor/xdg_wm_base to open a popup with", file!(), line!());
+                return None;
+            }
+        };
+        if content.w == 0 || content.h == 0 {
+            return None;
+        }
+        self.next_id += 1;
+        let id = PopupId(self.next_id);
+
+        let surface = compositor.create_surface(qh, ());
+        if kind == PopupKind::Tooltip {
+            // An empty input region lets clicks fall through to the dock
+            let region = compositor.create_region(qh, ());
+            surface.set_input_region(Some(&region));
+            region.destroy();
+        }
+        let xdg_surface = wm_base.get_xdg_surface(&surface, qh, id);
+        let positioner = positioner(wm_base, content.w, content.h, &placement, qh);
+        let xdg_popup = xdg_surface.get_popup(Some(parent), &positioner, qh, id);
+        positioner.destroy();
+        if let Some((seat, serial)) = grab {
+            xdg_popup.grab(seat, serial);
+        }
+        surface.commit(); // The first configure arrives in response to this
+
+        self.list.push(Popup { id, kind, surface, xdg_surface, xdg_popup, placement, content, configured: false, buffer: None });
+        Some(id)
+    }
+
+    pub fn get(&self, id: PopupId) -> Option<&Popup> {
+        self.list.iter().find(|p| p.id == id)
+    }
+
+    pub fn by_surface(&self, surface: &wl_surface::WlSurface) -> Option<PopupId> {
+        self.list.iter().find(|p| p.surface == *surface).map(|p| p.id)
+    }
+
+    // Move an open popup to a new anchor; needs xdg_wm_base v3, otherwise it stays where it is
+    pub fn move_to(&mut self, id: PopupId, placement: Placement, qh: &QueueHandle<State>) {
+        let wm_base = match self.wm_base.as_ref() {
+            Some(wm_base) => wm_base,
+            None => return,
+        };
+        if let Some(popup) = self.list.iter_mut().find(|p| p.id == id) && popup.placement != placement {
+            popup.placement = placement;
+            if wayland_client::Proxy::version(&popup.xdg_popup) >= 3 {
+                let positioner = positioner(wm_base, popup.content.w, popup.content.h, &placement, qh);
+                popup.xdg_popup.reposition(&positioner, 0);
+                positioner.destroy();
+            }
+        }
+    }
+
+    // Replacement contents, eg a menu with a different row highlighted. A new size needs
+    // xdg_wm_base v3 to reposition the popup; otherwise it keeps the size it opened with.
+    pub fn set_content(&mut self, id: PopupId, content: Image, qh: &QueueHandle<State>) {
+        let shm = self.shm.clone();
+        let wm_base = self.wm_base.clone();
+        if let Some(popup) = self.list.iter_mut().find(|p| p.id == id) {
+            let resized = (content.w, content.h) != (popup.content.w, popup.content.h);
+            popup.content = content;
+            if resized && let Some(wm_base) = wm_base.as_ref() && wayland_client::Proxy::version(&popup.xdg_popup) >= 3 {
+                // The new buffer goes up with the configure that answers this
+                let positioner = positioner(wm_base, popup.content.w, popup.content.h, &popup.placement, qh);
+                popup.xdg_popup.reposition(&positioner, 0);
+                positioner.destroy();
+                return;
+            }
+            if popup.configured && let Some(shm) = shm.as_ref() {
+                popup.present(shm, qh);
+            }
+        }
+    }
+
+    pub fn close(&mut self, id: PopupId) {
+        if let Some(i) = self.list.iter().position(|p| p.id == id) {
+            self.list.remove(i).destroy();
+        }
+    }
+
+    pub fn close_kind(&mut self, kind: PopupKind) {
+        let (closing, kept): (Vec<Popup>, Vec<Popup>) = self.list.drain(..).partition(|p| p.kind == kind);
+        self.list = kept;
+        for popup in closing {
+            popup.destroy();
+        }
+    }
+}
+
+impl Popup {
+    fn destroy(self) {
+        // Children before parents, as xdg-shell requires
+        self.xdg_popup.destroy();
+        self.xdg_surface.destroy();
+        self.surface.destroy();
+        if let Some(buffer) = self.buffer {
+            buffer.destroy();
+        }
+    }
+
+    // Copy `content` into a fresh shm buffer and show it
+    fn present(&mut self, shm: &wl_shm::WlShm, qh: &QueueHandle<State>) {
+        let (w, h) = (self.content.w as i32, self.content.h as i32);
+        let file = match crate::util::create_shm_fd() {
+            Ok(fd) => std::fs::File::from(fd),
+            Err(e) => {
+                eprintln!("{}:{} {:?}", file!(), line!(), e);
+                return;
+            }
+        };
+        let bytes: &[u8] = unsafe { std::slice::from_raw_parts(self.content.px.as_ptr() as *const u8, self.content.px.len() * 4) };
+        let mut writer = std::io::BufWriter::new(&file);
+        if let Err(e) = writer.write_all(bytes).and_then(|_| writer.flush()) {
+            eprintln!("{}:{} {:?}", file!(), line!(), e);
+            return;
+        }
+        drop(writer);
+
+        use std::os::fd::AsFd;
+        let pool = shm.create_pool(file.as_fd(), w * h * 4, qh, ());
+        let buffer = pool.create_buffer(0, w, h, w * 4, wl_shm::Format::Argb8888, qh, ());
+        pool.destroy();
+        self.surface.attach(Some(&buffer), 0, 0);
+        self.surface.damage(0, 0, w, h);
+        self.surface.commit();
+        if let Some(old) = self.buffer.replace(buffer) {
+            old.destroy();
+        }
+    }
+}
+
+fn positioner(wm_base: &xdg_wm_base::XdgWmBase, w: u32, h: u32, placement: &Placement, qh: &QueueHandle<State>) -> xdg_positioner::XdgPositioner {
+    use xdg_positioner::{Anchor, ConstraintAdjustment, Gravity};
+    let positioner = wm_base.create_positioner(qh, ());
+    positioner.set_size(w as i32, h as i32);
+    let r = placement.rect;
+    positioner.set_anchor_rect(r.x, r.y, r.w.max(1), r.h.max(1));
+    let (anchor, gravity, (dx, dy)) = match (placement.center_offset, placement.edge) {
+        (Some(offset), _) => (Anchor::None, Gravity::None, offset),
+        (None, Edge::Bottom) => (Anchor::Top, Gravity::Top, (0, -placement.gap)),
+        (None, Edge::Top) => (Anchor::Bottom, Gravity::Bottom, (0, placement.gap)),
+        (None, Edge::Left) => (Anchor::Right, Gravity::Right, (placement.gap, 0)),
+        (None, Edge::Right) => (Anchor::Left, Gravity::Left, (-placement.gap, 0)),
+    };
+    positioner.set_anchor(anchor);
+    positioner.set_gravity(gravity);
+    positioner.set_offset(dx, dy);
+    positioner.set_constraint_adjustment(ConstraintAdjustment::SlideX | ConstraintAdjustment::SlideY
+                                         | ConstraintAdjustment::FlipX | ConstraintAdjustment::FlipY);
+    positioner
+}
+
+impl Dispatch<xdg_surface::XdgSurface, PopupId> for State {
+    fn event(
+        state: &mut Self,
+        xdg_surface: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        id: &PopupId,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            xdg_surface.ack_configure(serial);
+            let shm = state.popups.shm.clone();
+            if let (Some(popup), Some(shm)) = (state.popups.list.iter_mut().find(|p| p.id == *id), shm.as_ref()) {
+                popup.configured = true;
+                popup.present(shm, qh);
+            }
+        }
+    }
+}
+
+impl Dispatch<xdg_popup::XdgPopup, PopupId> for State {
+    fn event(
+        state: &mut Self,
+        _: &xdg_popup::XdgPopup,
+        event: xdg_popup::Event,
+        id: &PopupId,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            // Outside click while grabbed, or the compositor had some other reason
+            xdg_popup::Event::PopupDone => {
+                state.popups.close(*id);
+                state.on_popup_dismissed(*id);
+            }
+            // Our content has a fixed size, so the suggested geometry is only informative
+            xdg_popup::Event::Configure { .. } => { }
+            _ => { }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+
+use std::path::PathBuf;
+
+use crate::dock::DockItem;
+
+// Recently opened documents, from the recently-used.xbel file GTK and most other
+// toolkits write to; see https://www.freedesktop.org/wiki/Specifications/desktop-bookmark-spec/
+
+// One document as last opened by one application
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentFile {
+    pub uri: String,
+    // The file name, for menus
+    pub name: String,
+    // ISO 8601 in UTC, so later times also sort later as strings
+    pub modified: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Bookmark {
+    uri: String,
+    apps: Vec<BookmarkApp>,
+}
+
+// A <bookmark:application> element: who opened the document, with what command, and when
+#[derive(Debug, Clone, PartialEq)]
+struct BookmarkApp {
+    name: String,
+    exec: String,
+    modified: String,
+}
+
+#[derive(Debug, Default)]
+pub struct RecentFiles {
+    bookmarks: Vec<Bookmark>,
+}
+
+pub fn xbel_path() -> PathBuf {
+    crate::desktop::data_dirs().into_iter().next().unwrap_or_default().join("recently-used.xbel")
+}
+
+impl RecentFiles {
+    // A missing or unreadable file just means no recent documents
+    pub fn load() -> RecentFiles {
+        let path = xbel_path();
+        match std::fs::read_to_string(&path) {
+            Ok(xml) => RecentFiles { bookmarks: parse(&xml) },
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("{}:{} {:?}: {}", file!(), line!(), path, e);
+                }
+                RecentFiles::default()
+            }
+        }
+    }
+
+    // The item's documents, most recently opened first, skipping local files that are gone
+    pub fn for_item(&self, item: &DockItem, limit: usize) -> Vec<RecentFile> {
+        let mut files: Vec<RecentFile> = self.bookmarks.iter()
+            .filter_map(|bookmark| {
+                let app = bookmark.apps.iter().filter(|app| app_matches(app, item)).max_by(|a, b| a.modified.cmp(&b.modified))?;
+                Some(RecentFile { uri: bookmark.uri.clone(), name: display_name(&bookmark.uri), modified: app.modified.clone() })
+            })
+            .filter(|file| crate::desktop::uri_to_path(&file.uri).map(|p| p.exists()).unwrap_or(true))
+            .collect();
+        files.sort_by(|a, b| b.modified.cmp(&a.modified));
+        files.truncate(limit);
+        files
+    }
+}
+
+// Toolkits record the application name (often the binary name) and the command they were run
+// with, so either can identify the dock item
+fn app_matches(app: &BookmarkApp, item: &DockItem) -> bool {
+    let by_name = !app.name.is_empty()
+        && (app.name.eq_ignore_ascii_case(&item.name) || item.matches_app_id(&app.name));
+    let item_program = item.exec.as_deref().and_then(|exec| crate::desktop::exec_argv(exec).into_iter().next());
+    let by_program = match (program_name(&app.exec), item_program.as_deref().map(program_name)) {
+        (Some(a), Some(Some(b))) => a == b && !LAUNCHERS.contains(&a.as_str()),
+        _ => false,
+    };
+    by_name || by_program
+}
+
+// Commands that run some other program, so sharing one says nothing about being the same app
+const LAUNCHERS: &[&str] = &["env", "flatpak", "snap", "sh", "bash", "gtk-launch"];
+
+// "'/usr/bin/gedit' %u" -> "gedit"; GLib quotes the command with single quotes
+fn program_name(exec: &str) -> Option<String> {
+    let first = exec.trim().trim_start_matches(['\'', '"']).split(|c: char| c.is_whitespace() || c == '\'' || c == '"').next()?;
+    let name = first.rsplit('/').next()?;
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+fn display_name(uri: &str) -> String {
+    let last = uri.trim_end_matches('/').rsplit('/').next().unwrap_or(uri);
+    String::from_utf8_lossy(&crate::desktop::percent_decode(last)).to_string()
+}
+
+// Just enough XML for xbel: element names and attributes, no nesting checks
+fn parse(xml: &str) -> Vec<Bookmark> {
+    let mut bookmarks: Vec<Bookmark> = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        rest = &rest[end + 1..];
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or("");
+        match name {
+            "bookmark" => {
+                if let Some(uri) = attribute(tag, "href") {
+                    bookmarks.push(Bookmark { uri, apps: vec![] });
+                }
+            }
+            "bookmark:application" => {
+                if let Some(bookmark) = bookmarks.last_mut() {
+                    bookmark.apps.push(BookmarkApp {
+                        name: attribute(tag, "name").unwrap_or_default(),
+                        exec: attribute(tag, "exec").unwrap_or_default(),
+                        modified: attribute(tag, "modified").unwrap_or_default(),
+                    });
+                }
+            }
+            _ => { }
+        }
+    }
+    bookmarks
+}
+
+fn attribute(tag: &str, key: &str) -> Option<String> {
+    let mut search = tag;
+    loop {
+        let at = search.find(key)?;
+        let before = search[..at].chars().next_back();
+        let after = search[at + key.len()..].trim_start();
+        search = &search[at + key.len()..];
+        if !before.map(|c| c.is_whitespace()).unwrap_or(false) {
+            continue; // Part of a longer name, eg "href" in "xhref"
+        }
+        let value = match after.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        return Some(unescape(&value[..value.find(quote)?]));
+    }
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let semi = match rest.find(';') {
+            Some(semi) => semi,
+            None => break,
+        };
+        let decoded = match &rest[1..semi] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => entity.strip_prefix("#x").map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse::<u32>()))
+                .and_then(|n| n.ok())
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
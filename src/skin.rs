@@ -0,0 +1,107 @@
+
+use std::path::Path;
+
+use crate::canvas::{Canvas, Image};
+use crate::layout::DockLayout;
+use crate::theme::SkinTheme;
+
+// Image-based shelf: painted left cap, tiled middle and right cap, stretched to the
+// shelf height with nine-slice rules so the painted top and bottom edges keep their size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skin {
+    pub left_cap: Image,
+    pub middle: Image,
+    pub right_cap: Image,
+    pub lip: Option<Image>,
+    pub indicator: Option<Image>,
+    pub slice_top: u32,
+    pub slice_bottom: u32,
+}
+
+impl Skin {
+    // Asset paths are relative to the directory holding the theme file
+    pub fn load(skin: &SkinTheme, theme_dir: &Path) -> Result<Skin, Box<dyn std::error::Error>> {
+        let load = |name: &str| -> Result<Image, Box<dyn std::error::Error>> {
+            crate::icons::load_png(&theme_dir.join(name)).map_err(|e| format!("skin image {:?}: {}", name, e).into())
+        };
+        let loaded = Skin {
+            left_cap: load(&skin.left_cap)?,
+            middle: load(&skin.middle)?,
+            right_cap: load(&skin.right_cap)?,
+            lip: skin.lip.as_deref().map(load).transpose()?,
+            indicator: skin.indicator.as_deref().map(load).transpose()?,
+            slice_top: skin.slice_top,
+            slice_bottom: skin.slice_bottom,
+        };
+        for (key, img) in [("skin.left_cap", &loaded.left_cap), ("skin.middle", &loaded.middle), ("skin.right_cap", &loaded.right_cap)] {
+            if img.w == 0 || img.h == 0 {
+                return Err(format!("{} is an empty image", key).into());
+            }
+            if skin.slice_top + skin.slice_bottom >= img.h {
+                return Err(format!("skin.slice_top + skin.slice_bottom = {} must be less than the {}px height of {}",
+                                   skin.slice_top + skin.slice_bottom, img.h, key).into());
+            }
+        }
+        Ok(loaded)
+    }
+
+    pub fn draw_shelf(&self, canvas: &mut Canvas, layout: &DockLayout) {
+        let shelf_h = layout.band_h;
+        let left = nine_slice_vertical(&self.left_cap, shelf_h, self.slice_top, self.slice_bottom);
+        let middle = nine_slice_vertical(&self.middle, shelf_h, self.slice_top, self.slice_bottom);
+        let right = nine_slice_vertical(&self.right_cap, shelf_h, self.slice_top, self.slice_bottom);
+
+        // Caps keep their painted width unless the shelf is too narrow for them
+        let cap_max_w = (layout.dock_w / 2).max(1) as u32;
+        let left = if left.w > cap_max_w { left.scaled(cap_max_w, left.h) } else { left };
+        let right = if right.w > cap_max_w { right.scaled(cap_max_w, right.h) } else { right };
+
+        let middle_start = layout.dock_x + left.w as i32;
+        let middle_end = layout.dock_x + layout.dock_w - right.w as i32;
+        tile_horizontal(canvas, &middle, middle_start, middle_end, 0);
+        canvas.blit(&left, layout.dock_x, 0, 1.0);
+        canvas.blit(&right, middle_end, 0, 1.0);
+
+        if let Some(lip) = self.lip.as_ref() {
+            let lip_y = shelf_h as i32 - lip.h as i32;
+            tile_horizontal(canvas, lip, layout.dock_x, layout.dock_x + layout.dock_w, lip_y);
+        }
+    }
+}
+
+// Keep the top `slice_top` and bottom `slice_bottom` rows as painted, stretch the rows between
+pub fn nine_slice_vertical(img: &Image, target_h: u32, slice_top: u32, slice_bottom: u32) -> Image {
+    if target_h <= slice_top + slice_bottom {
+        return img.scaled(img.w, target_h.max(1));
+    }
+    let src_middle_h = img.h - slice_top - slice_bottom;
+    let dst_middle_h = target_h - slice_top - slice_bottom;
+    let mut out = Image::new(img.w, target_h);
+    for y in 0..target_h {
+        let src_y = if y < slice_top {
+            y
+        }
+        else if y >= target_h - slice_bottom {
+            img.h - (target_h - y)
+        }
+        else {
+            slice_top + (((y - slice_top) * src_middle_h) / dst_middle_h).min(src_middle_h - 1)
+        };
+        let src_row = &img.px[(src_y * img.w) as usize..((src_y + 1) * img.w) as usize];
+        out.px[(y * img.w) as usize..((y + 1) * img.w) as usize].copy_from_slice(src_row);
+    }
+    out
+}
+
+// Repeat `img` from x_start up to (not including) x_end, cutting the last copy short
+pub fn tile_horizontal(canvas: &mut Canvas, img: &Image, x_start: i32, x_end: i32, y: i32) {
+    if img.w == 0 {
+        return;
+    }
+    for x in x_start..x_end {
+        let src_x = ((x - x_start) as u32) % img.w;
+        for iy in 0..img.h {
+            canvas.blend(x, y + iy as i32, img.px[((iy * img.w) + src_x) as usize], 1.0);
+        }
+    }
+}
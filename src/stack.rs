@@ -0,0 +1,388 @@
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::{Canvas, Image};
+use crate::icons::IconCache;
+use crate::layout::{Edge, Rect};
+use crate::mime::MimeDb;
+use crate::popup::PopupId;
+use crate::text::TextRenderer;
+use crate::theme::{Color, Theme};
+
+// Folder stacks: clicking a folder's item shows what is in it, fanned out in an arc rising
+// from the dock or as a scrollable grid. Clicking a file opens it with its default app.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackView {
+    // A fan when everything fits in one, otherwise a grid
+    #[default]
+    Auto,
+    Fan,
+    Grid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackSort {
+    #[default]
+    Name,
+    // Last modified, newest first
+    Date,
+}
+
+// How a folder item shows its stack; saved with the pinned item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StackOptions {
+    pub view: StackView,
+    pub sort: StackSort,
+}
+
+// Past this many files a folder is better browsed in the file manager
+const MAX_ENTRIES: usize = 300;
+// Files in a fan; the rest are left to the file manager
+const FAN_MAX: usize = 10;
+const FAN_ICON_PX: i32 = 48;
+const FAN_PITCH_PX: i32 = 56;
+// How far the fan has curved sideways by the top, in icon widths
+const FAN_CURVE: f32 = 0.9;
+const FAN_LABEL_MAX_W: u32 = 240;
+const GRID_ICON_PX: i32 = 48;
+const GRID_CELL_W: i32 = 96;
+const GRID_COLUMNS: usize = 5;
+const GRID_ROWS: usize = 4;
+const SCROLLBAR_W: i32 = 4;
+
+#[derive(Debug, Clone)]
+pub struct StackEntry {
+    pub path: PathBuf,
+    pub name: String,
+    // Icon theme names to try, best first
+    pub icons: Vec<String>,
+    pub modified: SystemTime,
+}
+
+// The folder's visible contents, sorted; hidden files are left out like file managers do
+pub fn list(dir: &Path, sort: StackSort, mime: &MimeDb) -> Vec<StackEntry> {
+    let read = match std::fs::read_dir(dir) {
+        Ok(read) => read,
+        Err(e) => {
+            eprintln!("{}:{} {:?}: {}", file!(), line!(), dir, e);
+            return vec![];
+        }
+    };
+    let mut entries: Vec<StackEntry> = read
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| {
+            let path = e.path();
+            StackEntry {
+                name: e.file_name().to_string_lossy().to_string(),
+                icons: mime.icon_names(&mime.for_path(&path)),
+                modified: e.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH),
+                path,
+            }
+        })
+        .collect();
+    match sort {
+        StackSort::Name => entries.sort_by_key(|e| e.name.to_lowercase()),
+        StackSort::Date => entries.sort_by_key(|e| std::cmp::Reverse(e.modified)),
+    }
+    entries.truncate(MAX_ENTRIES);
+    entries
+}
+
+// What the pointer can pick in an open stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackHit {
+    Entry(usize),
+    // "Open in File Manager"
+    Open,
+}
+
+// One pickable thing and where its parts go, in popup surface coordinates
+struct Cell {
+    hit: StackHit,
+    area: Rect,
+    icon: Option<Rect>,
+    // Top-left corner of its label
+    label: (i32, i32),
+}
+
+// An open stack. The listing is a snapshot, refreshed when the folder changes.
+pub struct Stack {
+    // DockItem::id() of the folder item
+    pub item_id: String,
+    pub dir: PathBuf,
+    pub options: StackOptions,
+    // Fan or Grid; Auto is settled when the stack opens
+    pub view: StackView,
+    pub entries: Vec<StackEntry>,
+    // Per entry (normal, highlighted), already shortened to fit
+    labels: Vec<(Image, Image)>,
+    open_label: (Image, Image),
+    title: Image,
+    pub hovered: Option<StackHit>,
+    // First grid row shown
+    pub scroll: usize,
+    pub popup: Option<PopupId>,
+}
+
+impl Stack {
+    pub fn new(item_id: &str, dir: &Path, options: StackOptions, edge: Edge, mime: &MimeDb, text: &TextRenderer, theme: &Theme) -> Stack {
+        let entries = list(dir, options.sort, mime);
+        // The fan only rises from a bottom dock; elsewhere it would run off the screen sideways
+        let fan = edge == Edge::Bottom && match options.view {
+            StackView::Auto => entries.len() <= FAN_MAX,
+            StackView::Fan => true,
+            StackView::Grid => false,
+        };
+        let view = if fan { StackView::Fan } else { StackView::Grid };
+        let mut stack = Stack {
+            item_id: item_id.to_string(),
+            dir: dir.to_path_buf(),
+            options,
+            view,
+            entries,
+            labels: vec![],
+            open_label: (Image::default(), Image::default()),
+            title: Image::default(),
+            hovered: None,
+            scroll: 0,
+            popup: None,
+        };
+        stack.render_labels(text, theme);
+        stack
+    }
+
+    // Re-read the folder after it changed, keeping the view and as much of the scroll as still fits
+    pub fn refresh(&mut self, mime: &MimeDb, text: &TextRenderer, theme: &Theme) {
+        self.entries = list(&self.dir, self.options.sort, mime);
+        self.render_labels(text, theme);
+        self.scroll = self.scroll.min(self.max_scroll());
+        if let Some(StackHit::Entry(i)) = self.hovered && i >= self.shown() {
+            self.hovered = None;
+        }
+    }
+
+    fn render_labels(&mut self, text: &TextRenderer, theme: &Theme) {
+        let menu = &theme.menu;
+        let folder_name = self.dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_else(|| self.dir.to_string_lossy().to_string());
+        let more = self.entries.len().saturating_sub(self.shown());
+        let open = match more {
+            0 => "Open in File Manager".to_string(),
+            n => format!("{} More in File Manager", n),
+        };
+        match self.view {
+            StackView::Fan => {
+                let plain = (theme.label.color, theme.label.background);
+                let lit = (menu.highlight_color, menu.highlight);
+                self.labels = self.entries.iter().take(FAN_MAX).map(|e| {
+                    let name = fit(text, &e.name, theme.label.size_px, FAN_LABEL_MAX_W);
+                    (pill(text, &name, plain, theme), pill(text, &name, lit, theme))
+                }).collect();
+                self.open_label = (pill(text, &open, plain, theme), pill(text, &open, lit, theme));
+            }
+            _ => {
+                let max_w = (GRID_CELL_W - 8) as u32;
+                self.labels = self.entries.iter().map(|e| {
+                    let name = fit(text, &e.name, menu.size_px, max_w);
+                    (text.render(&name, menu.size_px, menu.color.bgra_premul()), text.render(&name, menu.size_px, menu.highlight_color.bgra_premul()))
+                }).collect();
+                self.open_label = (text.render(&open, menu.size_px, menu.color.bgra_premul()), text.render(&open, menu.size_px, menu.highlight_color.bgra_premul()));
+                self.title = text.render(&folder_name, menu.size_px, menu.disabled_color.bgra_premul());
+            }
+        }
+    }
+
+    // Entries the view has room for; a grid shows them all by scrolling
+    fn shown(&self) -> usize {
+        match self.view {
+            StackView::Fan => self.entries.len().min(FAN_MAX),
+            _ => self.entries.len(),
+        }
+    }
+
+    fn columns(&self) -> usize {
+        self.entries.len().clamp(3, GRID_COLUMNS)
+    }
+
+    fn rows(&self) -> usize {
+        self.entries.len().div_ceil(self.columns())
+    }
+
+    pub fn max_scroll(&self) -> usize {
+        match self.view {
+            StackView::Fan => 0,
+            _ => self.rows().saturating_sub(GRID_ROWS),
+        }
+    }
+
+    // Scroll the grid by `rows`, which may be negative; true if anything moved
+    pub fn scroll_by(&mut self, rows: i32) -> bool {
+        let scroll = (self.scroll as i32 + rows).clamp(0, self.max_scroll() as i32) as usize;
+        let moved = scroll != self.scroll;
+        self.scroll = scroll;
+        moved
+    }
+
+    pub fn hit(&self, (x, y): (f64, f64), theme: &Theme) -> Option<StackHit> {
+        let (_, cells) = self.layout(theme);
+        cells.iter().find(|cell| cell.area.contains(x, y)).map(|cell| cell.hit)
+    }
+
+    // Popup size and where everything goes in it
+    fn layout(&self, theme: &Theme) -> ((i32, i32), Vec<Cell>) {
+        match self.view {
+            StackView::Fan => self.fan_layout(theme),
+            _ => self.grid_layout(theme),
+        }
+    }
+
+    // Bottom row first, each a little further right than the one below it, labels on the left
+    fn fan_layout(&self, theme: &Theme) -> ((i32, i32), Vec<Cell>) {
+        let gap = theme.label.gap_px as i32;
+        let n = self.labels.len();
+        let curve = |k: usize| (FAN_CURVE * FAN_ICON_PX as f32 * (k as f32 / FAN_MAX as f32).powi(2)).round() as i32;
+        // Measured from the column above the dock icon, which ends up in the middle of the popup
+        let mut left = FAN_ICON_PX / 2;
+        let mut right = FAN_ICON_PX / 2;
+        for (k, (label, _)) in self.labels.iter().enumerate() {
+            left = left.max(FAN_ICON_PX / 2 - curve(k) + gap + label.w as i32);
+            right = right.max(FAN_ICON_PX / 2 + curve(k));
+        }
+        let open_w = self.open_label.0.w as i32;
+        left = left.max(open_w / 2 - curve(n));
+        right = right.max(open_w / 2 + curve(n));
+        let w = 2 * left.max(right);
+        let h = (n as i32 + 1) * FAN_PITCH_PX;
+        let cx = w / 2;
+
+        let mut cells = vec![];
+        for (k, (label, _)) in self.labels.iter().enumerate() {
+            let row_top = h - ((k as i32 + 1) * FAN_PITCH_PX);
+            let icon = Rect { x: cx - (FAN_ICON_PX / 2) + curve(k), y: row_top + ((FAN_PITCH_PX - FAN_ICON_PX) / 2), w: FAN_ICON_PX, h: FAN_ICON_PX };
+            let label_pos = (icon.x - gap - label.w as i32, row_top + ((FAN_PITCH_PX - label.h as i32) / 2));
+            let area = Rect { x: label_pos.0, y: row_top, w: icon.x + icon.w - label_pos.0, h: FAN_PITCH_PX };
+            cells.push(Cell { hit: StackHit::Entry(k), area, icon: Some(icon), label: label_pos });
+        }
+        let (open, _) = &self.open_label;
+        let label_pos = (cx + curve(n) - (open.w as i32 / 2), (FAN_PITCH_PX - open.h as i32) / 2);
+        cells.push(Cell { hit: StackHit::Open, area: Rect { x: label_pos.0, y: label_pos.1, w: open.w as i32, h: open.h as i32 }, icon: None, label: label_pos });
+        ((w, h), cells)
+    }
+
+    // Folder name, the visible rows of files, then "Open in File Manager"
+    fn grid_layout(&self, theme: &Theme) -> ((i32, i32), Vec<Cell>) {
+        let style = &theme.menu;
+        let pad = style.padding_px as i32;
+        let row_h = style.row_height_px as i32;
+        let cell_h = GRID_ICON_PX + row_h;
+        let columns = self.columns();
+        let visible_rows = self.rows().min(GRID_ROWS) as i32;
+        let scrollbar = if self.max_scroll() > 0 { SCROLLBAR_W + pad } else { 0 };
+        let w = (2 * pad) + (columns as i32 * GRID_CELL_W) + scrollbar;
+        let h = (2 * pad) + row_h + (visible_rows * cell_h) + row_h;
+
+        let mut cells = vec![];
+        let first = self.scroll * columns;
+        let last = (first + (GRID_ROWS * columns)).min(self.labels.len());
+        for i in first..last {
+            let (row, col) = (((i - first) / columns) as i32, ((i - first) % columns) as i32);
+            let area = Rect { x: pad + (col * GRID_CELL_W), y: pad + row_h + (row * cell_h), w: GRID_CELL_W, h: cell_h };
+            let icon = Rect { x: area.x + ((GRID_CELL_W - GRID_ICON_PX) / 2), y: area.y + (row_h / 4), w: GRID_ICON_PX, h: GRID_ICON_PX };
+            let label = &self.labels[i].0;
+            let label_pos = (area.x + ((GRID_CELL_W - label.w as i32) / 2), icon.y + icon.h + ((row_h - label.h as i32) / 2));
+            cells.push(Cell { hit: StackHit::Entry(i), area, icon: Some(icon), label: label_pos });
+        }
+        let open = Rect { x: pad, y: h - pad - row_h, w: w - (2 * pad), h: row_h };
+        let label = &self.open_label.0;
+        let label_pos = (open.x + ((open.w - label.w as i32) / 2), open.y + ((row_h - label.h as i32) / 2));
+        cells.push(Cell { hit: StackHit::Open, area: open, icon: None, label: label_pos });
+        ((w, h), cells)
+    }
+
+    pub fn render(&self, icons: &mut IconCache, theme: &Theme) -> Image {
+        let ((w, h), cells) = self.layout(theme);
+        let mut img = Image::new(w.max(1) as u32, h.max(1) as u32);
+        let mut canvas = Canvas::new(&mut img.px, w.max(1) as u32, h.max(1) as u32);
+        let style = &theme.menu;
+        let pad = style.padding_px as i32;
+        let row_h = style.row_height_px as i32;
+        let radius = (style.corner_radius as i32 - pad).max(3);
+
+        if self.view == StackView::Grid {
+            crate::menu::draw_panel(&mut canvas, w, h, style);
+            canvas.blit(&self.title, (w - self.title.w as i32) / 2, pad + ((row_h - self.title.h as i32) / 2), 1.0);
+            if self.max_scroll() > 0 {
+                let track = Rect { x: w - pad - SCROLLBAR_W, y: pad + row_h, w: SCROLLBAR_W, h: h - (2 * pad) - (2 * row_h) };
+                let thumb_h = (track.h * GRID_ROWS as i32 / self.rows() as i32).max(SCROLLBAR_W * 2);
+                let thumb_y = track.y + ((track.h - thumb_h) * self.scroll as i32 / self.max_scroll() as i32);
+                canvas.fill_rounded_rect(track.x, thumb_y, track.w, thumb_h, SCROLLBAR_W / 2, style.separator.bgra_premul());
+            }
+        }
+
+        for cell in cells.iter() {
+            let hovered = self.hovered == Some(cell.hit);
+            let (label, highlighted) = match cell.hit {
+                StackHit::Entry(i) => &self.labels[i],
+                StackHit::Open => &self.open_label,
+            };
+            // Fan labels are pills that light up themselves; grid cells light up whole
+            if hovered && self.view == StackView::Grid {
+                canvas.fill_rounded_rect(cell.area.x, cell.area.y, cell.area.w, cell.area.h, radius, style.highlight.bgra_premul());
+            }
+            if let (StackHit::Entry(i), Some(icon)) = (cell.hit, cell.icon) {
+                draw_icon(&mut canvas, icons, &self.entries[i].icons, icon);
+            }
+            canvas.blit(if hovered { highlighted } else { label }, cell.label.0, cell.label.1, 1.0);
+        }
+        img
+    }
+}
+
+// A file name on a rounded pill, like the dock's own labels but in the given (text, background) colors
+fn pill(text: &TextRenderer, label: &str, (color, background): (Color, Color), theme: &Theme) -> Image {
+    let pad = theme.label.padding_px as i32;
+    let rendered = text.render(label, theme.label.size_px, color.bgra_premul());
+    let (box_w, box_h) = (rendered.w as i32 + (2 * pad), rendered.h as i32 + pad);
+    let mut img = Image::new(box_w as u32, box_h as u32);
+    let mut canvas = Canvas::new(&mut img.px, box_w as u32, box_h as u32);
+    canvas.fill_rounded_rect(0, 0, box_w, box_h, box_h / 2, background.bgra_premul());
+    canvas.blit(&rendered, pad, pad / 2, 1.0);
+    img
+}
+
+// The first of `names` the icon theme has, or a neutral tile
+fn draw_icon(canvas: &mut Canvas, icons: &mut IconCache, names: &[String], rect: Rect) {
+    let size = rect.w as u32;
+    match names.iter().find(|name| icons.get(name, size).is_some()).and_then(|name| icons.get(name, size)) {
+        Some(img) => canvas.blit(img, rect.x, rect.y, 1.0),
+        None => {
+            let inset = rect.w / 8;
+            canvas.fill_rounded_rect(rect.x + inset, rect.y, rect.w - (2 * inset), rect.h, rect.w / 8, [0xE0, 0xE0, 0xE0, 0xF0]);
+        }
+    }
+}
+
+// `name` shortened with "..." until it renders no wider than max_w
+fn fit(text: &TextRenderer, name: &str, size_px: f32, max_w: u32) -> String {
+    let rendered_w = text.render(name, size_px, [0xFF; 4]).w;
+    if rendered_w <= max_w {
+        return name.to_string();
+    }
+    let chars: Vec<char> = name.chars().collect();
+    // Start from a guess in proportion to the overflow, then trim until it fits
+    let mut keep = (chars.len() * max_w as usize / rendered_w.max(1) as usize).min(chars.len());
+    while keep > 1 {
+        let candidate = format!("{}...", chars[..keep].iter().collect::<String>().trim_end());
+        if text.render(&candidate, size_px, [0xFF; 4]).w <= max_w {
+            return candidate;
+        }
+        keep -= 1;
+    }
+    format!("{}...", chars.first().map(|c| c.to_string()).unwrap_or_default())
+}
@@ -0,0 +1,199 @@
+
+use std::path::{Path, PathBuf};
+
+use ab_glyph::{Font as _, FontVec, PxScale, ScaleFont as _};
+
+use crate::canvas::Image;
+
+// Where glyphs come from: a system font found the way fontconfig would, or the tiny
+// bitmap font below when there is no usable font on the machine at all.
+pub enum Font {
+    Outline(FontVec),
+    Bitmap,
+}
+
+pub struct TextRenderer {
+    pub font: Font,
+    // The family it was loaded for, so theme reloads can tell whether anything changed
+    pub family: String,
+}
+
+impl TextRenderer {
+    pub fn load(family: &str) -> TextRenderer {
+        let font = match find_font(family).and_then(|(path, index)| load_font(&path, index)) {
+            Some(font) => Font::Outline(font),
+            None => {
+                eprintln!("{}:{} no usable font for {:?}, using the built-in bitmap font", file!(), line!(), family);
+                Font::Bitmap
+            }
+        };
+        TextRenderer { font, family: family.to_string() }
+    }
+
+    // `text` rasterized in `color` ([b, g, r, a] premultiplied), cropped to a line box
+    // of the font's ascent + descent and the text's advance width
+    pub fn render(&self, text: &str, px_size: f32, color: [u8; 4]) -> Image {
+        match &self.font {
+            Font::Outline(font) => render_outline(font, text, px_size, color),
+            Font::Bitmap => render_bitmap(text, px_size, color),
+        }
+    }
+}
+
+// fc-match knows the user's configured defaults and aliases; scanning font dirs is the fallback
+fn find_font(family: &str) -> Option<(PathBuf, u32)> {
+    let fc_match = std::process::Command::new("fc-match")
+        .args(["-f", "%{file}\n%{index}\n", family])
+        .output();
+    if let Ok(output) = fc_match && output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        if let Some(path) = lines.next().filter(|p| !p.is_empty()) {
+            let index = lines.next().and_then(|i| i.trim().parse().ok()).unwrap_or(0);
+            return Some((PathBuf::from(path), index));
+        }
+    }
+    scan_font_dirs().map(|path| (path, 0))
+}
+
+// Common sans fonts first, then anything that looks loadable
+fn scan_font_dirs() -> Option<PathBuf> {
+    const PREFERRED: [&str; 4] = ["DejaVuSans.ttf", "NotoSans-Regular.ttf", "LiberationSans-Regular.ttf", "Cantarell-Regular.otf"];
+    let mut dirs = vec![PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".local/share/fonts")];
+    dirs.extend(crate::desktop::data_dirs().into_iter().map(|d| d.join("fonts")));
+
+    let mut candidates = vec![];
+    for dir in dirs.iter() {
+        collect_fonts(dir, &mut candidates, 4);
+    }
+    PREFERRED.iter()
+        .find_map(|name| candidates.iter().find(|p| p.file_name().map(|n| n == *name).unwrap_or(false)))
+        .or_else(|| candidates.first())
+        .cloned()
+}
+
+fn collect_fonts(dir: &Path, out: &mut Vec<PathBuf>, depth: u32) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth > 0 {
+                collect_fonts(&path, out, depth - 1);
+            }
+        }
+        else if matches!(path.extension().and_then(|e| e.to_str()), Some("ttf") | Some("otf")) {
+            out.push(path);
+        }
+    }
+}
+
+fn load_font(path: &Path, index: u32) -> Option<FontVec> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}:{} {:?}: {}", file!(), line!(), path, e);
+            return None;
+        }
+    };
+    match FontVec::try_from_vec_and_index(bytes, index) {
+        Ok(font) => Some(font),
+        Err(e) => {
+            eprintln!("{}:{} {:?}: {}", file!(), line!(), path, e);
+            None
+        }
+    }
+}
+
+fn render_outline(font: &FontVec, text: &str, px_size: f32, color: [u8; 4]) -> Image {
+    let scaled = font.as_scaled(PxScale::from(px_size));
+    let ascent = scaled.ascent();
+    let line_h = (ascent - scaled.descent()).ceil().max(1.0);
+
+    // Lay out on a single line with kerning
+    let mut glyphs = vec![];
+    let mut x = 0.0;
+    let mut prev = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(prev) = prev {
+            x += scaled.kern(prev, id);
+        }
+        glyphs.push(id.with_scale_and_position(scaled.scale(), ab_glyph::point(x, ascent)));
+        x += scaled.h_advance(id);
+        prev = Some(id);
+    }
+
+    let mut img = Image::new(x.ceil().max(1.0) as u32, line_h as u32);
+    for glyph in glyphs {
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= img.w as i32 || py >= img.h as i32 {
+                    return;
+                }
+                // Overlapping glyph edges add up rather than double-darken
+                let dst = &mut img.px[((py as u32 * img.w) + px as u32) as usize];
+                for c in 0..4 {
+                    dst[c] = (dst[c] as f32 + (color[c] as f32 * coverage)).min(255.0) as u8;
+                }
+            });
+        }
+    }
+    img
+}
+
+// Drawn at 1px per dot, then scaled up; the bilinear filter softens the blocky edges
+fn render_bitmap(text: &str, px_size: f32, color: [u8; 4]) -> Image {
+    let chars: Vec<char> = text.chars().collect();
+    let advance = BITMAP_GLYPH_W + 1;
+    let mut img = Image::new(((chars.len() as u32) * advance).max(1), BITMAP_GLYPH_H);
+    for (i, c) in chars.iter().enumerate() {
+        let code = *c as u32;
+        let glyph = if (0x20..0x7F).contains(&code) { BITMAP_FONT[(code - 0x20) as usize] } else { BITMAP_FONT[('?' as u32 - 0x20) as usize] };
+        for (col, bits) in glyph.iter().enumerate() {
+            for row in 0..BITMAP_GLYPH_H {
+                if bits & (1 << row) != 0 {
+                    img.px[((row * img.w) + (i as u32 * advance) + col as u32) as usize] = color;
+                }
+            }
+        }
+    }
+    let scale = px_size / BITMAP_GLYPH_H as f32;
+    img.scaled((img.w as f32 * scale).round().max(1.0) as u32, (img.h as f32 * scale).round().max(1.0) as u32)
+}
+
+const BITMAP_GLYPH_W: u32 = 5;
+const BITMAP_GLYPH_H: u32 = 8;
+
+// Printable ASCII (0x20..=0x7E), 5 columns per glyph, bit 0 = top row; the classic 5x7 LCD font plus descenders
+const BITMAP_FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00], [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62], [0x36, 0x49, 0x56, 0x20, 0x50], [0x00, 0x08, 0x07, 0x03, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00], [0x00, 0x41, 0x22, 0x1C, 0x00], [0x2A, 0x1C, 0x7F, 0x1C, 0x2A], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x80, 0x70, 0x30, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x00, 0x60, 0x60, 0x00], [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00], [0x72, 0x49, 0x49, 0x49, 0x46], [0x21, 0x41, 0x49, 0x4D, 0x33],
+    [0x18, 0x14, 0x12, 0x7F, 0x10], [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x31], [0x41, 0x21, 0x11, 0x09, 0x07],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x46, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x00, 0x14, 0x00, 0x00], [0x00, 0x40, 0x34, 0x00, 0x00],
+    [0x00, 0x08, 0x14, 0x22, 0x41], [0x14, 0x14, 0x14, 0x14, 0x14], [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x59, 0x09, 0x06],
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E], [0x7C, 0x12, 0x11, 0x12, 0x7C], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x41, 0x3E], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01], [0x3E, 0x41, 0x41, 0x51, 0x73],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00], [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40], [0x7F, 0x02, 0x1C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46], [0x26, 0x49, 0x49, 0x49, 0x32],
+    [0x03, 0x01, 0x7F, 0x01, 0x03], [0x3F, 0x40, 0x40, 0x40, 0x3F], [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63], [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x59, 0x49, 0x4D, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x41],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x41, 0x7F], [0x04, 0x02, 0x01, 0x02, 0x04], [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x03, 0x07, 0x08, 0x00], [0x20, 0x54, 0x54, 0x78, 0x40], [0x7F, 0x28, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x28],
+    [0x38, 0x44, 0x44, 0x28, 0x7F], [0x38, 0x54, 0x54, 0x54, 0x18], [0x00, 0x08, 0x7E, 0x09, 0x02], [0x18, 0xA4, 0xA4, 0x9C, 0x78],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x40, 0x3D, 0x00], [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x78, 0x04, 0x78], [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0xFC, 0x18, 0x24, 0x24, 0x18], [0x18, 0x24, 0x24, 0x18, 0xFC], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x24],
+    [0x04, 0x04, 0x3F, 0x44, 0x24], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C], [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44], [0x4C, 0x90, 0x90, 0x90, 0x7C], [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x77, 0x00, 0x00], [0x00, 0x41, 0x36, 0x08, 0x00], [0x02, 0x01, 0x02, 0x04, 0x02],
+];
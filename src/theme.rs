@@ -0,0 +1,361 @@
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// Bundled so the dock always has something to draw, even with no themes installed
+pub const LEOPARD_TOML: &str = include_str!("../themes/leopard.toml");
+pub const DEFAULT_THEME_NAME: &str = "leopard";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    // [b, g, r, a] with premultiplied alpha, ready for px_buf / Canvas
+    pub fn bgra_premul(&self) -> [u8; 4] {
+        let premul = |c: u8| ((c as u32 * self.a as u32) / 255) as u8;
+        [premul(self.b), premul(self.g), premul(self.r), self.a]
+    }
+
+    pub fn bgr(&self) -> [u8; 3] {
+        [self.b, self.g, self.r]
+    }
+}
+
+impl std::str::FromStr for Color {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Color, String> {
+        let hex = s.strip_prefix('#').ok_or_else(|| format!("color {:?} must start with '#'", s))?;
+        if (hex.len() != 6 && hex.len() != 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("color {:?} must be \"#rrggbb\" or \"#rrggbbaa\"", s));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+        Ok(Color {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+            a: if hex.len() == 8 { channel(6) } else { 0xFF },
+        })
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, self.a)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+// Sections missing from a user theme fall back to Leopard's, so a theme can be a one-line tweak
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "leopard_shelf")]
+    pub shelf: ShelfTheme,
+    #[serde(default = "leopard_shadow")]
+    pub shadow: ShadowTheme,
+    #[serde(default = "leopard_reflection")]
+    pub reflection: ReflectionTheme,
+    #[serde(default = "leopard_lip")]
+    pub lip: LipTheme,
+    #[serde(default = "leopard_indicator")]
+    pub indicator: IndicatorTheme,
+    #[serde(default = "leopard_material")]
+    pub material: MaterialTheme,
+    #[serde(default = "leopard_label")]
+    pub label: LabelTheme,
+    #[serde(default = "leopard_floor")]
+    pub floor: FloorTheme,
+    #[serde(default = "leopard_menu")]
+    pub menu: MenuTheme,
+    #[serde(default = "leopard_flat")]
+    pub flat: FlatTheme,
+    // Present for image-based themes; replaces the procedural shelf entirely
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skin: Option<SkinTheme>,
+    // The decoded `skin` images, filled in by Theme::load
+    #[serde(skip)]
+    pub skin_images: Option<crate::skin::Skin>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShelfTheme {
+    pub angle_deg: i32,
+    pub fallback_alpha: u8,
+    pub fallback_gradient: Gradient,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gradient {
+    pub top_left: Color,
+    pub top_right: Color,
+    pub bottom_left: Color,
+    pub bottom_right: Color,
+}
+
+impl Gradient {
+    // Each corner's color fades linearly toward the two far edges, and the strongest wins; the
+    // original shelf's fallback colors. (x, y) is a pixel in a w×h area. Returns [b, g, r].
+    pub fn sample(&self, x: u32, y: u32, w: u32, h: u32) -> [u8; 3] {
+        let (x, y, w, h) = (x.min(w), y.min(h), w.max(1), h.max(1));
+        let corners = [(&self.top_left, w - x, h - y), (&self.top_right, x, h - y), (&self.bottom_left, w - x, y), (&self.bottom_right, x, y)];
+        let channel = |pick: fn(&Color) -> u8| corners.iter()
+            .map(|(c, wx, wy)| ((wx * pick(c) as u32) / w).min((wy * pick(c) as u32) / h))
+            .max().unwrap_or(0) as u8;
+        [channel(|c| c.b), channel(|c| c.g), channel(|c| c.r)]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShadowTheme {
+    pub width_px: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReflectionTheme {
+    pub opacity: f32,
+    pub tint: Color,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LipTheme {
+    pub height_px: u32,
+    pub color: Color,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndicatorTheme {
+    pub color: Color,
+    pub core_color: Color,
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialTheme {
+    pub texture: [u8; 16],
+    pub strength: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelTheme {
+    pub font: String,
+    pub size_px: f32,
+    pub color: Color,
+    pub background: Color,
+    pub padding_px: u32,
+    pub gap_px: u32,
+}
+
+// Right-click menus; text uses the [label] font
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MenuTheme {
+    pub size_px: f32,
+    pub color: Color,
+    pub disabled_color: Color,
+    pub background: Color,
+    pub border: Color,
+    pub separator: Color,
+    pub highlight: Color,
+    pub highlight_color: Color,
+    pub corner_radius: u32,
+    pub padding_px: u32,
+    pub row_height_px: u32,
+    pub min_width_px: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FloorTheme {
+    pub reflection_opacity: f32,
+    pub reflection_squash: f32,
+    pub shadow_opacity: f32,
+    pub shadow_width: f32,
+    pub shadow_depth: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlatTheme {
+    pub fill: Color,
+    pub border: Color,
+    pub inner_glow: Color,
+    pub glow_width_px: u32,
+    pub corner_radius: u32,
+    pub shadow_alpha: f32,
+}
+
+// PNG file names, relative to the theme file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SkinTheme {
+    pub left_cap: String,
+    pub middle: String,
+    pub right_cap: String,
+    #[serde(default)]
+    pub lip: Option<String>,
+    #[serde(default)]
+    pub indicator: Option<String>,
+    // Rows at the top/bottom of each shelf image that are never stretched
+    #[serde(default)]
+    pub slice_top: u32,
+    #[serde(default)]
+    pub slice_bottom: u32,
+}
+
+impl SkinTheme {
+    pub fn asset_names(&self) -> Vec<&str> {
+        let mut names = vec![self.left_cap.as_str(), self.middle.as_str(), self.right_cap.as_str()];
+        names.extend(self.lip.as_deref());
+        names.extend(self.indicator.as_deref());
+        names
+    }
+}
+
+fn leopard_shelf() -> ShelfTheme { Theme::leopard().shelf }
+fn leopard_shadow() -> ShadowTheme { Theme::leopard().shadow }
+fn leopard_reflection() -> ReflectionTheme { Theme::leopard().reflection }
+fn leopard_lip() -> LipTheme { Theme::leopard().lip }
+fn leopard_indicator() -> IndicatorTheme { Theme::leopard().indicator }
+fn leopard_material() -> MaterialTheme { Theme::leopard().material }
+fn leopard_label() -> LabelTheme { Theme::leopard().label }
+fn leopard_floor() -> FloorTheme { Theme::leopard().floor }
+fn leopard_menu() -> MenuTheme { Theme::leopard().menu }
+fn leopard_flat() -> FlatTheme { Theme::leopard().flat }
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::leopard()
+    }
+}
+
+impl Theme {
+    pub fn leopard() -> Theme {
+        // leopard.toml lists every key, so none of the leopard_* defaults above recurse back here
+        toml::from_str(LEOPARD_TOML).expect("bundled themes/leopard.toml must parse")
+    }
+
+    pub fn parse(contents: &str, source: &str) -> Result<Theme, Box<dyn std::error::Error>> {
+        let theme = toml::from_str::<Theme>(contents).map_err(|e| format!("{}: {}", source, e))?;
+        theme.validate().map_err(|e| format!("{}: {}", source, e))?;
+        Ok(theme)
+    }
+
+    // `name_or_path` is what config.toml's `theme` says: a bare name looked up in the
+    // theme directories, or a path to a .toml file.
+    pub fn load(name_or_path: &str) -> Result<Theme, Box<dyn std::error::Error>> {
+        match find_theme_file(name_or_path) {
+            Some(path) => {
+                let contents = std::fs::read_to_string(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+                let mut theme = Theme::parse(&contents, &path.to_string_lossy())?;
+                if let Some(skin) = theme.skin.as_ref() {
+                    let theme_dir = path.parent().unwrap_or(Path::new("."));
+                    let skin_images = crate::skin::Skin::load(skin, theme_dir).map_err(|e| format!("{}: {}", path.to_string_lossy(), e))?;
+                    theme.skin_images = Some(skin_images);
+                }
+                Ok(theme)
+            }
+            None if name_or_path.eq_ignore_ascii_case(DEFAULT_THEME_NAME) => Ok(Theme::leopard()),
+            None => Err(format!("No theme named {:?} in {:?}", name_or_path, theme_dirs()).into()),
+        }
+    }
+
+    // Every message names the offending key and the accepted range
+    pub fn validate(&self) -> Result<(), String> {
+        check_range("shelf.angle_deg", self.shelf.angle_deg as f32, 0.0, 89.0)?;
+        check_range("shadow.width_px", self.shadow.width_px as f32, 1.0, 128.0)?;
+        check_range("reflection.opacity", self.reflection.opacity, 0.0, 1.0)?;
+        check_range("lip.height_px", self.lip.height_px as f32, 0.0, 64.0)?;
+        check_range("indicator.radius", self.indicator.radius, 0.01, 1.0)?;
+        check_range("indicator.intensity", self.indicator.intensity, 0.0, 1.0)?;
+        check_range("material.strength", self.material.strength, 0.0, 4.0)?;
+        check_range("label.size_px", self.label.size_px, 4.0, 96.0)?;
+        check_range("label.padding_px", self.label.padding_px as f32, 0.0, 64.0)?;
+        check_range("label.gap_px", self.label.gap_px as f32, 0.0, 64.0)?;
+        check_range("menu.size_px", self.menu.size_px, 4.0, 96.0)?;
+        check_range("menu.corner_radius", self.menu.corner_radius as f32, 0.0, 64.0)?;
+        check_range("menu.padding_px", self.menu.padding_px as f32, 0.0, 64.0)?;
+        check_range("menu.row_height_px", self.menu.row_height_px as f32, 8.0, 128.0)?;
+        check_range("menu.min_width_px", self.menu.min_width_px as f32, 0.0, 2048.0)?;
+        check_range("floor.reflection_opacity", self.floor.reflection_opacity, 0.0, 1.0)?;
+        check_range("floor.reflection_squash", self.floor.reflection_squash, 0.05, 1.0)?;
+        check_range("floor.shadow_opacity", self.floor.shadow_opacity, 0.0, 1.0)?;
+        check_range("floor.shadow_width", self.floor.shadow_width, 0.0, 2.0)?;
+        check_range("floor.shadow_depth", self.floor.shadow_depth, 0.0, 1.0)?;
+        check_range("flat.glow_width_px", self.flat.glow_width_px as f32, 0.0, 64.0)?;
+        check_range("flat.corner_radius", self.flat.corner_radius as f32, 0.0, 128.0)?;
+        check_range("flat.shadow_alpha", self.flat.shadow_alpha, 0.0, 1.0)?;
+        Ok(())
+    }
+}
+
+fn check_range(key: &str, value: f32, min: f32, max: f32) -> Result<(), String> {
+    if value.is_nan() || value < min || value > max {
+        return Err(format!("{} = {} is outside the allowed range {}..={}", key, value, min, max));
+    }
+    Ok(())
+}
+
+// $XDG_CONFIG_HOME/sdock/themes, then sdock/themes under each XDG data dir
+pub fn theme_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![crate::config::config_dir().join("themes")];
+    for data_dir in crate::desktop::data_dirs() {
+        dirs.push(data_dir.join("sdock").join("themes"));
+    }
+    dirs
+}
+
+// The theme file plus any skin images it references; all of these trigger a reload when edited
+pub fn theme_files(name_or_path: &str) -> Vec<PathBuf> {
+    let path = match find_theme_file(name_or_path) {
+        Some(path) => path,
+        None => return vec![],
+    };
+    let mut files = vec![];
+    let skin = std::fs::read_to_string(&path).ok()
+        .and_then(|contents| toml::from_str::<Theme>(&contents).ok())
+        .and_then(|theme| theme.skin);
+    if let (Some(skin), Some(theme_dir)) = (skin, path.parent()) {
+        files.extend(skin.asset_names().into_iter().map(|name| theme_dir.join(name)));
+    }
+    files.push(path);
+    files
+}
+
+pub fn find_theme_file(name_or_path: &str) -> Option<PathBuf> {
+    if name_or_path.contains('/') {
+        let path = Path::new(name_or_path);
+        return if path.exists() { Some(path.to_path_buf()) } else { None };
+    }
+    theme_dirs().into_iter()
+        .map(|dir| dir.join(format!("{}.toml", name_or_path)))
+        .find(|path| path.exists())
+}
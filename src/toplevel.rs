@@ -0,0 +1,345 @@
+
+// Window tracking. Two protocols can tell us about other clients' windows:
+//  - zwlr_foreign_toplevel_manager_v1 (wlroots, Hyprland, ...): app_id, title, state + control requests
+//  - ext_foreign_toplevel_list_v1 (niri, COSMIC, KWin, ...): app_id + title only
+// Whichever the registry advertises is used; if both are, wlr wins because it lets us control windows.
+
+use wayland_client::{
+    event_created_child,
+    protocol::{wl_registry, wl_seat, wl_surface},
+    Connection, Dispatch, QueueHandle,
+};
+
+use wayland_protocols::ext::foreign_toplevel_list::v1::client::{
+    ext_foreign_toplevel_handle_v1, ext_foreign_toplevel_list_v1,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
+};
+
+use crate::dock::DockItem;
+use crate::layout::{DockLayout, Rect};
+use crate::State;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Handle {
+    Wlr(zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1),
+    Ext(ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1),
+}
+
+impl Handle {
+    fn destroy(&self) {
+        match self {
+            Handle::Wlr(handle) => handle.destroy(),
+            Handle::Ext(handle) => handle.destroy(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Backend {
+    Wlr(zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1),
+    Ext(ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1),
+}
+
+// One window belonging to some other client, as reported by the compositor.
+#[derive(Debug, Clone)]
+pub struct Toplevel {
+    pub handle: Handle,
+    pub app_id: String,
+    pub title: String,
+    // The ext backend never reports these, so they stay false there
+    pub maximized: bool,
+    pub minimized: bool,
+    pub activated: bool,
+    pub fullscreen: bool,
+    // Larger = focused more recently; 0 = never seen focused
+    pub focus_serial: u64,
+    // Last icon rectangle sent with set_rectangle, so we only send changes
+    pub minimize_rect: Option<Rect>,
+}
+
+impl Toplevel {
+    fn new(handle: Handle) -> Toplevel {
+        Toplevel {
+            handle,
+            app_id: String::new(),
+            title: String::new(),
+            maximized: false,
+            minimized: false,
+            activated: false,
+            fullscreen: false,
+            focus_serial: 0,
+            minimize_rect: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Toplevels {
+    pub backend: Option<Backend>,
+    pub list: Vec<Toplevel>,
+    focus_counter: u64,
+}
+
+impl Toplevels {
+    pub fn bind_wlr(&mut self, registry: &wl_registry::WlRegistry, name: u32, version: u32, qh: &QueueHandle<State>) {
+        if let Some(Backend::Wlr(_)) = self.backend {
+            return;
+        }
+        if let Some(Backend::Ext(list)) = self.backend.take() {
+            // Advertised before wlr; drop it and let wlr report every window again
+            eprintln!("{}:{} switching window tracking from ext_foreign_toplevel_list_v1 to wlr", file!(), line!());
+            list.stop();
+            for toplevel in self.list.drain(..) {
+                toplevel.handle.destroy();
+            }
+        }
+        let manager = registry.bind::<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, _, _>(name, std::cmp::min(version, 3), qh, ());
+        self.backend = Some(Backend::Wlr(manager));
+    }
+
+    pub fn bind_ext(&mut self, registry: &wl_registry::WlRegistry, name: u32, version: u32, qh: &QueueHandle<State>) {
+        if self.backend.is_some() {
+            return;
+        }
+        let list = registry.bind::<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, _, _>(name, std::cmp::min(version, 1), qh, ());
+        self.backend = Some(Backend::Ext(list));
+    }
+
+    fn find_mut(&mut self, handle: &Handle) -> Option<&mut Toplevel> {
+        self.list.iter_mut().find(|t| &t.handle == handle)
+    }
+
+    // Windows that should show up in the dock; our own surface is excluded.
+    pub fn visible(&self) -> impl Iterator<Item = &Toplevel> {
+        self.list.iter().filter(|t| !t.app_id.is_empty() && t.app_id != "sdock")
+    }
+
+    // Only the wlr protocol lets us activate/minimize other clients' windows
+    pub fn supports_control(&self) -> bool {
+        matches!(self.backend, Some(Backend::Wlr(_)))
+    }
+
+    pub fn activate(&self, handle: &Handle, seat: &wl_seat::WlSeat) {
+        match handle {
+            Handle::Wlr(handle) => {
+                handle.unset_minimized();
+                handle.activate(seat);
+            }
+            Handle::Ext(_) => {
+                eprintln!("{}:{} ext_foreign_toplevel_list_v1 cannot activate windows", file!(), line!());
+            }
+        }
+    }
+
+    // Tell the compositor where each window's dock icon is so minimize animations fly toward it.
+    // Must be re-run whenever the layout changes or windows come and go.
+    pub fn update_minimize_rects(&mut self, surface: &wl_surface::WlSurface, items: &[DockItem], layout: &DockLayout) {
+        for toplevel in self.list.iter_mut() {
+            let handle = match &toplevel.handle {
+                Handle::Wlr(handle) => handle,
+                Handle::Ext(_) => continue, // No equivalent request
+            };
+            let rect = items.iter()
+                .position(|item| item.matches_app_id(&toplevel.app_id))
+                .and_then(|i| layout.icons.get(i).copied())
+                .unwrap_or_default(); // 0x0 removes a previously set rectangle
+            if toplevel.minimize_rect != Some(rect) {
+                handle.set_rectangle(surface, rect.x, rect.y, rect.w, rect.h);
+                toplevel.minimize_rect = Some(rect);
+            }
+        }
+    }
+
+    pub fn minimize(&self, handle: &Handle) {
+        match handle {
+            Handle::Wlr(handle) => {
+                handle.set_minimized();
+            }
+            Handle::Ext(_) => {
+                eprintln!("{}:{} ext_foreign_toplevel_list_v1 cannot minimize windows", file!(), line!());
+            }
+        }
+    }
+
+    // Politely; the app may still ask about unsaved work
+    pub fn close(&self, handle: &Handle) {
+        match handle {
+            Handle::Wlr(handle) => {
+                handle.close();
+            }
+            Handle::Ext(_) => {
+                eprintln!("{}:{} ext_foreign_toplevel_list_v1 cannot close windows", file!(), line!());
+            }
+        }
+    }
+}
+
+// Protocol-independent half of event handling
+
+fn on_new(state: &mut State, handle: Handle) {
+    state.toplevels.list.push(Toplevel::new(handle));
+}
+
+fn on_title(state: &mut State, handle: &Handle, title: String) {
+    if let Some(toplevel) = state.toplevels.find_mut(handle) {
+        toplevel.title = title;
+    }
+}
+
+fn on_app_id(state: &mut State, handle: &Handle, app_id: String) {
+    if let Some(toplevel) = state.toplevels.find_mut(handle) {
+        toplevel.app_id = app_id;
+    }
+}
+
+fn on_closed(state: &mut State, handle: &Handle) {
+    state.toplevels.list.retain(|t| &t.handle != handle);
+    handle.destroy();
+    state.on_toplevels_changed();
+}
+
+fn on_finished(state: &mut State) {
+    eprintln!("{}:{} compositor stopped sending toplevel events", file!(), line!());
+    state.toplevels.backend = None;
+    state.toplevels.list.clear();
+    state.on_toplevels_changed();
+}
+
+// zwlr_foreign_toplevel_manager_v1 backend
+
+impl Dispatch<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } => {
+                on_new(state, Handle::Wlr(toplevel));
+            }
+            zwlr_foreign_toplevel_manager_v1::Event::Finished => {
+                on_finished(state);
+            }
+            _ => { }
+        }
+    }
+
+    event_created_child!(State, zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let handle = Handle::Wlr(handle.clone());
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                on_title(state, &handle, title);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                on_app_id(state, &handle, app_id);
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state: state_bytes } => {
+                let next_focus_serial = state.toplevels.focus_counter + 1;
+                if let Some(toplevel) = state.toplevels.find_mut(&handle) {
+                    // Array of native-endian u32 enum values
+                    let states: Vec<u32> = state_bytes.chunks_exact(4)
+                        .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                        .collect();
+                    let was_activated = toplevel.activated;
+                    toplevel.maximized = states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Maximized as u32));
+                    toplevel.minimized = states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Minimized as u32));
+                    toplevel.activated = states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Activated as u32));
+                    toplevel.fullscreen = states.contains(&(zwlr_foreign_toplevel_handle_v1::State::Fullscreen as u32));
+                    if toplevel.activated && !was_activated {
+                        toplevel.focus_serial = next_focus_serial;
+                        state.toplevels.focus_counter = next_focus_serial;
+                    }
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                state.on_toplevels_changed();
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                on_closed(state, &handle);
+            }
+            _ => { }
+        }
+    }
+}
+
+// ext_foreign_toplevel_list_v1 backend
+
+impl Dispatch<ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        list: &ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let is_current = matches!(&state.toplevels.backend, Some(Backend::Ext(current)) if current == list);
+        match event {
+            ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } => {
+                if is_current {
+                    on_new(state, Handle::Ext(toplevel));
+                }
+                else {
+                    toplevel.destroy(); // Raced with a switch to the wlr backend
+                }
+            }
+            ext_foreign_toplevel_list_v1::Event::Finished => {
+                list.destroy();
+                if is_current {
+                    on_finished(state);
+                }
+            }
+            _ => { }
+        }
+    }
+
+    event_created_child!(State, ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let handle = Handle::Ext(handle.clone());
+        match event {
+            ext_foreign_toplevel_handle_v1::Event::Title { title } => {
+                on_title(state, &handle, title);
+            }
+            ext_foreign_toplevel_handle_v1::Event::AppId { app_id } => {
+                on_app_id(state, &handle, app_id);
+            }
+            ext_foreign_toplevel_handle_v1::Event::Done => {
+                state.on_toplevels_changed();
+            }
+            ext_foreign_toplevel_handle_v1::Event::Closed => {
+                on_closed(state, &handle);
+            }
+            _ => { }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// The home trash from https://specifications.freedesktop.org/trash-spec/latest/
+// A trashed file moves to Trash/files, and Trash/info/<same name>.trashinfo records
+// where it came from and when, so file managers can put it back.
+// Files on other filesystems would need that filesystem's own $topdir/.Trash; those are refused.
+
+pub fn trash_dir() -> PathBuf {
+    crate::desktop::data_dirs().into_iter().next().unwrap_or_default().join("Trash")
+}
+
+pub fn files_dir() -> PathBuf {
+    trash_dir().join("files")
+}
+
+fn info_dir() -> PathBuf {
+    trash_dir().join("info")
+}
+
+// The spec lets implementations create the trash whenever it is needed; it has to be private
+pub fn create_dirs() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::DirBuilderExt;
+    for dir in [files_dir(), info_dir()] {
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir).map_err(crate::err::eloc!(format!("{:?}", dir)))?;
+    }
+    Ok(())
+}
+
+// A missing trash is an empty one
+pub fn is_empty() -> bool {
+    std::fs::read_dir(files_dir()).map(|mut entries| entries.next().is_none()).unwrap_or(true)
+}
+
+// Move `path` into the trash. Returns its name in Trash/files.
+pub fn trash(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let path = std::path::absolute(path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    if path.starts_with(trash_dir()) {
+        return Err(format!("{:?} is already in the trash", path).into());
+    }
+    let file_name = path.file_name().ok_or_else(|| format!("{:?} has no file name to trash it under", path))?;
+    std::fs::symlink_metadata(&path).map_err(crate::err::eloc!(format!("{:?}", path)))?;
+    create_dirs()?;
+
+    // The info file is created first, exclusively, to claim the name against other trashers
+    let (name, info_path) = claim_name(&file_name.to_string_lossy())?;
+    let info = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", crate::desktop::percent_encode_path(&path), deletion_date());
+    let moved = std::fs::OpenOptions::new().write(true).open(&info_path)
+        .and_then(|mut file| file.write_all(info.as_bytes()))
+        .and_then(|_| std::fs::rename(&path, files_dir().join(&name)));
+    if let Err(e) = moved {
+        let _ = std::fs::remove_file(&info_path);
+        return match e.raw_os_error() {
+            Some(nix::libc::EXDEV) => Err(format!("{:?} is on a different filesystem from the trash", path).into()),
+            _ => Err(format!("{}:{} {:?}: {}", file!(), line!(), path, e).into()),
+        };
+    }
+    Ok(name)
+}
+
+// "notes.txt", then "notes.2.txt", "notes.3.txt"... until one is free in both files and info
+fn claim_name(file_name: &str) -> Result<(String, PathBuf), Box<dyn std::error::Error>> {
+    let (stem, ext) = match file_name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (file_name, String::new()),
+    };
+    for n in 1.. {
+        let name = if n == 1 { file_name.to_string() } else { format!("{}.{}{}", stem, n, ext) };
+        if files_dir().join(&name).symlink_metadata().is_ok() {
+            continue; // Left behind without its info file
+        }
+        let info_path = info_dir().join(format!("{}.trashinfo", name));
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(_) => return Ok((name, info_path)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("{}:{} {:?}: {}", file!(), line!(), info_path, e).into()),
+        }
+    }
+    unreachable!()
+}
+
+// Local time without a timezone, as the spec asks: "2004-08-31T22:32:08"
+fn deletion_date() -> String {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as nix::libc::time_t;
+    let mut tm: nix::libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { nix::libc::localtime_r(&now, &mut tm) }.is_null() {
+        return "1970-01-01T00:00:00".to_string();
+    }
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+// Delete everything in the trash. Runs in the background since the trash can be big;
+// the watch on Trash/files notices when it is done.
+pub fn empty() {
+    std::thread::spawn(|| {
+        // Files first; info left behind by an interrupted empty is ignored, as the spec says
+        for dir in [files_dir(), info_dir()] {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|e| e.ok()) {
+                let path = entry.path();
+                let removed = match entry.file_type() {
+                    Ok(file_type) if file_type.is_dir() => std::fs::remove_dir_all(&path),
+                    _ => std::fs::remove_file(&path),
+                };
+                if let Err(e) = removed {
+                    eprintln!("{}:{} {:?}: {}", file!(), line!(), path, e);
+                }
+            }
+        }
+        // A cache of Trash/files sizes that would now be stale
+        let _ = std::fs::remove_file(trash_dir().join("directorysizes"));
+    });
+}
@@ -0,0 +1,105 @@
+
+use std::ffi::OsString;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+
+// Which reload a file change should trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Config,
+    Theme,
+    RecentFiles,
+    // The folder of the open stack
+    Stack,
+    // Trash/files, for the trash's empty or full icon
+    Trash,
+}
+
+// Watches are placed on parent directories rather than the files themselves: editors and
+// Config::save() replace files by renaming over them, which silently kills a per-file watch.
+struct DirWatch {
+    wd: WatchDescriptor,
+    dir: PathBuf,
+    // (file name, kind); a None file name means any change in the directory counts
+    files: Vec<(Option<OsString>, WatchKind)>,
+}
+
+pub struct Watcher {
+    inotify: Inotify,
+    dirs: Vec<DirWatch>,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Watcher, Box<dyn std::error::Error>> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC).map_err(crate::err::eloc!())?;
+        Ok(Watcher { inotify, dirs: vec![] })
+    }
+
+    pub fn watch_file(&mut self, path: &Path, kind: WatchKind) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = path.parent().unwrap_or(Path::new("/"));
+        let file_name = path.file_name().map(|n| n.to_os_string());
+        self.watch(dir, file_name, kind)
+    }
+
+    pub fn watch_dir(&mut self, dir: &Path, kind: WatchKind) -> Result<(), Box<dyn std::error::Error>> {
+        self.watch(dir, None, kind)
+    }
+
+    fn watch(&mut self, dir: &Path, file_name: Option<OsString>, kind: WatchKind) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(existing) = self.dirs.iter_mut().find(|d| d.dir == dir) {
+            if !existing.files.contains(&(file_name.clone(), kind)) {
+                existing.files.push((file_name, kind));
+            }
+            return Ok(());
+        }
+        let flags = AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_MOVED_FROM
+                  | AddWatchFlags::IN_CREATE | AddWatchFlags::IN_DELETE;
+        let wd = self.inotify.add_watch(dir, flags).map_err(crate::err::eloc!(format!("{:?}", dir)))?;
+        self.dirs.push(DirWatch { wd, dir: dir.to_path_buf(), files: vec![(file_name, kind)] });
+        Ok(())
+    }
+
+    // Forget everything of one kind, eg before re-watching a theme the user switched away from
+    pub fn unwatch_kind(&mut self, kind: WatchKind) {
+        for dir in self.dirs.iter_mut() {
+            dir.files.retain(|(_, k)| *k != kind);
+        }
+        let (empty, kept): (Vec<DirWatch>, Vec<DirWatch>) = self.dirs.drain(..).partition(|d| d.files.is_empty());
+        self.dirs = kept;
+        for dir in empty {
+            let _ = self.inotify.rm_watch(dir.wd);
+        }
+    }
+
+    // Drains pending inotify events; each kind is reported at most once per call
+    pub fn read_changes(&self) -> Vec<WatchKind> {
+        let mut changed = vec![];
+        let events = match self.inotify.read_events() {
+            Ok(events) => events,
+            Err(nix::errno::Errno::EAGAIN) => return changed,
+            Err(e) => {
+                eprintln!("{}:{} {:?}", file!(), line!(), e);
+                return changed;
+            }
+        };
+        for event in events {
+            let dir = match self.dirs.iter().find(|d| d.wd == event.wd) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            for (file_name, kind) in dir.files.iter() {
+                let matches = file_name.is_none() || *file_name == event.name;
+                if matches && !changed.contains(kind) {
+                    changed.push(*kind);
+                }
+            }
+        }
+        changed
+    }
+
+    pub fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}